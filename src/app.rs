@@ -0,0 +1,79 @@
+//! Run path using the `ApplicationHandler` api of modern winit versions,
+//! enabled with the `app-handler` feature.
+
+use crate::{gfx_backend::GlesBackend, Demo, BACKEND_CONFIG};
+use raw_window_handle::{RawWindowHandle, XlibHandle};
+use winit30::{
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    raw_window_handle::{self as rwh_06, HasWindowHandle},
+    window::{Window, WindowId},
+};
+
+#[derive(Default)]
+struct App {
+    // the demo owns the gl surface, so it needs to be dropped before the window
+    demo: Option<Demo>,
+    window: Option<Window>,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+
+        let window = event_loop
+            .create_window(Window::default_attributes())
+            .unwrap();
+
+        let handle = legacy_handle(window.window_handle().unwrap().as_raw()).unwrap();
+        let backend = GlesBackend::from_handle(handle, &BACKEND_CONFIG).unwrap();
+        let mut demo = Demo::new(backend).unwrap();
+
+        let size = window.inner_size();
+        demo.resize(size.width, size.height);
+
+        self.demo = Some(demo);
+        self.window = Some(window);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        let demo = match self.demo.as_mut() {
+            Some(demo) => demo,
+            None => return,
+        };
+
+        match event {
+            WindowEvent::RedrawRequested => demo.redraw(),
+            WindowEvent::Resized(size) => demo.resize(size.width, size.height),
+            WindowEvent::CloseRequested => event_loop.exit(),
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+    }
+}
+
+/// Convert the handle to the `raw-window-handle` version of winit 0.26 used by the backend
+fn legacy_handle(handle: rwh_06::RawWindowHandle) -> Result<RawWindowHandle, String> {
+    match handle {
+        rwh_06::RawWindowHandle::Xlib(xlib) => {
+            let mut legacy = XlibHandle::empty();
+            legacy.window = xlib.window;
+            Ok(RawWindowHandle::Xlib(legacy))
+        }
+        _ => Err(format!("Unsupported window handle: {:?}", handle)),
+    }
+}
+
+pub fn run() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.run_app(&mut App::default()).unwrap();
+}
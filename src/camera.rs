@@ -1,18 +1,51 @@
-use glam::{Mat4, Vec3};
+use glam::Mat4;
 use std::f32::consts::PI;
 
 pub struct Camera {
-    pos: Vec3,
+    /// Fixed aspect ratio, overrides the one of the window size when set
+    fixed_aspect: Option<f32>,
+    aspect: f32,
+    proj: Mat4,
 }
 
 impl Camera {
     pub fn new() -> Self {
-        Self { pos: Vec3::ZERO }
+        Self {
+            fixed_aspect: None,
+            aspect: 1.0,
+            proj: Mat4::perspective_rh_gl(PI / 2.0, 1.0, 0.01, 1000.0),
+        }
     }
 
+    /// Aspect ratio used by the last projection
+    #[allow(dead_code)]
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// Use a fixed aspect ratio instead of the window one, for letterboxed rendering
+    #[allow(dead_code)]
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.fixed_aspect = Some(aspect);
+    }
+
+    /// Go back to the aspect ratio of the window size
+    #[allow(dead_code)]
+    pub fn clear_aspect(&mut self) {
+        self.fixed_aspect = None;
+    }
+
+    /// Returns the projection for the window size,
+    /// a zero sized window (minimized) keeps the last valid projection
     pub fn update(&mut self, size: (i32, i32)) -> Mat4 {
-        let proj = Mat4::perspective_rh_gl(PI / 2.0, size.0 as f32 / size.1 as f32, 0.01, 1000.0);
+        let aspect = match self.fixed_aspect {
+            Some(aspect) => aspect,
+            None if size.0 > 0 && size.1 > 0 => size.0 as f32 / size.1 as f32,
+            None => return self.proj,
+        };
 
-        proj
+        self.aspect = aspect;
+        self.proj = Mat4::perspective_rh_gl(PI / 2.0, aspect, 0.01, 1000.0);
+        self.proj
     }
 }
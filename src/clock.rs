@@ -0,0 +1,30 @@
+use std::time::Instant;
+
+/// Longest delta reported, avoids big jumps after a stall (like dragging the window)
+const MAX_DELTA: f32 = 0.25;
+
+/// Measures the time between frames so animations don't depend on the frame rate
+pub struct FrameClock {
+    last: Option<Instant>,
+    delta: f32,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            delta: 0.0,
+        }
+    }
+
+    /// Call once per frame, returns the seconds elapsed since the previous call
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        self.delta = match self.last {
+            Some(last) => (now - last).as_secs_f32().min(MAX_DELTA),
+            None => 0.0,
+        };
+        self.last = Some(now);
+        self.delta
+    }
+}
@@ -1,12 +1,22 @@
 #![allow(dead_code)]
 
+pub mod barrier;
 pub mod buffer;
 pub mod color;
 pub mod commands;
 pub mod device;
+pub mod draw2d;
 pub mod encoder;
+pub mod fence;
+pub mod graph;
 pub mod limits;
+pub mod material;
 pub mod pipeline;
+pub mod query;
 pub mod rect;
 pub mod render_texture;
+pub mod sampler;
+pub mod shader;
+pub mod stats;
+pub mod text;
 pub mod texture;
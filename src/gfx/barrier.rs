@@ -0,0 +1,92 @@
+/// Which kind of reads must see the writes done by the shaders before a memory barrier
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct BarrierFlags {
+    pub vertex_attrib: bool,
+    pub element_array: bool,
+    pub uniform: bool,
+    pub texture_fetch: bool,
+    pub shader_image_access: bool,
+    pub command: bool,
+    pub buffer_update: bool,
+    pub texture_update: bool,
+    pub framebuffer: bool,
+    pub shader_storage: bool,
+    pub pixel_buffer: bool,
+    pub transform_feedback: bool,
+    pub atomic_counter: bool,
+}
+
+impl BarrierFlags {
+    /// Wait for every kind of write, the safest and slowest option
+    pub const ALL: BarrierFlags = BarrierFlags {
+        vertex_attrib: true,
+        element_array: true,
+        uniform: true,
+        texture_fetch: true,
+        shader_image_access: true,
+        command: true,
+        buffer_update: true,
+        texture_update: true,
+        framebuffer: true,
+        shader_storage: true,
+        pixel_buffer: true,
+        transform_feedback: true,
+        atomic_counter: true,
+    };
+
+    pub const NONE: BarrierFlags = BarrierFlags {
+        vertex_attrib: false,
+        element_array: false,
+        uniform: false,
+        texture_fetch: false,
+        shader_image_access: false,
+        command: false,
+        buffer_update: false,
+        texture_update: false,
+        framebuffer: false,
+        shader_storage: false,
+        pixel_buffer: false,
+        transform_feedback: false,
+        atomic_counter: false,
+    };
+
+    /// Storage buffers written by a compute pass and read as vertices or indices
+    pub const VERTEX_DATA: BarrierFlags = BarrierFlags {
+        vertex_attrib: true,
+        element_array: true,
+        ..BarrierFlags::NONE
+    };
+
+    /// Images written by a compute pass and sampled as textures
+    pub const TEXTURE_SAMPLE: BarrierFlags = BarrierFlags {
+        texture_fetch: true,
+        ..BarrierFlags::NONE
+    };
+
+    /// Returns if no flag is set
+    pub fn is_empty(&self) -> bool {
+        *self == BarrierFlags::NONE
+    }
+}
+
+impl std::ops::BitOr for BarrierFlags {
+    type Output = BarrierFlags;
+
+    fn bitor(self, rhs: BarrierFlags) -> BarrierFlags {
+        BarrierFlags {
+            vertex_attrib: self.vertex_attrib || rhs.vertex_attrib,
+            element_array: self.element_array || rhs.element_array,
+            uniform: self.uniform || rhs.uniform,
+            texture_fetch: self.texture_fetch || rhs.texture_fetch,
+            shader_image_access: self.shader_image_access || rhs.shader_image_access,
+            command: self.command || rhs.command,
+            buffer_update: self.buffer_update || rhs.buffer_update,
+            texture_update: self.texture_update || rhs.texture_update,
+            framebuffer: self.framebuffer || rhs.framebuffer,
+            shader_storage: self.shader_storage || rhs.shader_storage,
+            pixel_buffer: self.pixel_buffer || rhs.pixel_buffer,
+            transform_feedback: self.transform_feedback || rhs.transform_feedback,
+            atomic_counter: self.atomic_counter || rhs.atomic_counter,
+        }
+    }
+}
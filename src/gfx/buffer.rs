@@ -3,8 +3,33 @@ use std::sync::Arc;
 use super::{
     device::{Device, DeviceBackend, DropManager, ResourceId},
     pipeline::DrawType,
+    shader::validate_vertex_attrs,
 };
 
+/// Index that breaks a strip when primitive restart is enabled on the encoder.
+/// GLES only supports the fixed restart index, the max value of the index format
+/// (`u32::MAX` here, `u16::MAX` or `u8::MAX` for the smaller formats)
+pub const PRIMITIVE_RESTART_INDEX: u32 = u32::MAX;
+
+/// Type of the indices of an index buffer, set by the type of the data uploaded to it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IndexFormat {
+    UInt8,
+    UInt16,
+    UInt32,
+}
+
+impl IndexFormat {
+    /// Size of one index in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            IndexFormat::UInt8 => 1,
+            IndexFormat::UInt16 => 2,
+            IndexFormat::UInt32 => 4,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct BufferIdRef {
     id: u64,
@@ -60,6 +85,8 @@ pub struct VertexBufferBuilder<'a, B: DeviceBackend> {
     data: Option<&'a [f32]>,
     vertex_attrs: Vec<VertexAttr>,
     vertex_step_mode: VertexStepMode,
+    stride: Option<u32>,
+    slot: u32,
 }
 
 impl<'a, B: DeviceBackend> VertexBufferBuilder<'a, B> {
@@ -69,6 +96,8 @@ impl<'a, B: DeviceBackend> VertexBufferBuilder<'a, B> {
             data: None,
             vertex_attrs: vec![],
             vertex_step_mode: VertexStepMode::Vertex,
+            stride: None,
+            slot: 0,
         }
     }
 
@@ -80,6 +109,25 @@ impl<'a, B: DeviceBackend> VertexBufferBuilder<'a, B> {
     pub fn with_info(mut self, info: &VertexInfo) -> Self {
         self.vertex_attrs = info.attrs.clone();
         self.vertex_step_mode = info.step_mode;
+        self.stride = info.stride;
+        self
+    }
+
+    /// Set the slot of the `VertexInfo` this buffer holds the attributes for
+    pub fn with_slot(mut self, slot: u32) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// Override the step mode of the `VertexInfo` for this buffer.
+    ///
+    /// With `VertexStepMode::Instance` the attributes advance once per instance
+    /// (`VertexAttribDivisor(1)`), so per instance data like a model matrix can live in its
+    /// own slot without the size limit of a uniform array. A `mat4` takes four `Float32x4`
+    /// attributes at consecutive locations, e.g. 2 to 5 for `layout(location = 2) in mat4`.
+    /// `VertexStepMode::InstanceEvery(n)` advances them every `n` instances instead.
+    pub fn with_step_mode(mut self, mode: VertexStepMode) -> Self {
+        self.vertex_step_mode = mode;
         self
     }
 
@@ -87,16 +135,21 @@ impl<'a, B: DeviceBackend> VertexBufferBuilder<'a, B> {
         let Self {
             device,
             data,
-            vertex_attrs,
+            mut vertex_attrs,
             vertex_step_mode,
+            stride,
+            slot,
         } = self;
 
+        vertex_attrs.retain(|attr| attr.slot == slot);
+
         debug_assert!(
             !vertex_attrs.is_empty(),
             "Missing vertex attributes for a VertexBuffer"
         );
 
-        device.inner_create_vertex_buffer(data, &vertex_attrs, vertex_step_mode)
+        let stride = vertex_stride(&vertex_attrs, stride)?;
+        device.inner_create_vertex_buffer(data, &vertex_attrs, vertex_step_mode, stride)
     }
 }
 
@@ -156,10 +209,45 @@ impl<'a, B: DeviceBackend> UniformBufferBuilder<'a, B> {
     }
 }
 
+pub struct StorageBufferBuilder<'a, B: DeviceBackend> {
+    device: &'a mut Device<B>,
+    data: Option<&'a [f32]>,
+    name: String,
+    slot: u32,
+}
+
+impl<'a, B: DeviceBackend> StorageBufferBuilder<'a, B> {
+    pub fn new(device: &'a mut Device<B>, slot: u32, name: &str) -> Self {
+        Self {
+            device,
+            data: None,
+            name: name.to_string(),
+            slot,
+        }
+    }
+
+    pub fn with_data(mut self, data: &'a [f32]) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn build(self) -> Result<Buffer, String> {
+        let Self {
+            device,
+            data,
+            name,
+            slot,
+        } = self;
+
+        device.inner_create_storage_buffer(slot, &name, data)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct VertexInfo {
     pub(crate) attrs: Vec<VertexAttr>,
     pub(crate) step_mode: VertexStepMode,
+    pub(crate) stride: Option<u32>,
 }
 
 impl VertexInfo {
@@ -172,10 +260,52 @@ impl VertexInfo {
         self
     }
 
+    /// Add an attribute for the shader input `name`, using the next free location.
+    /// The location is bound to the name when the pipeline is linked
+    pub fn attr_named(mut self, name: &'static str, format: VertexFormat) -> Self {
+        let location = self
+            .attrs
+            .iter()
+            .map(|attr| attr.location + 1)
+            .max()
+            .unwrap_or(0);
+        self.attrs
+            .push(VertexAttr::new(location, format).with_name(name));
+        self
+    }
+
+    /// Add an attribute read from the vertex buffer created for `slot`,
+    /// used to split the vertex data in several buffers (position, normal, uv...)
+    pub fn slot_attr(mut self, slot: u32, location: u32, format: VertexFormat) -> Self {
+        self.attrs
+            .push(VertexAttr::new(location, format).with_slot(slot));
+        self
+    }
+
+    /// Add an attribute at an explicit byte offset of the vertex,
+    /// the attributes added after it continue from its end
+    pub fn offset_attr(mut self, location: u32, format: VertexFormat, offset: u32) -> Self {
+        self.attrs
+            .push(VertexAttr::new(location, format).with_offset(offset));
+        self
+    }
+
     pub fn step_mode(mut self, mode: VertexStepMode) -> Self {
         self.step_mode = mode;
         self
     }
+
+    /// Bytes between two vertices, for padded layouts (e.g. 16 bytes aligned)
+    /// instead of the tightly packed size of the attributes
+    pub fn with_stride(mut self, bytes: u32) -> Self {
+        self.stride = Some(bytes);
+        self
+    }
+
+    /// Check the attributes against the `layout(location = N) in` declarations of the shader
+    pub fn validate_against(&self, vertex_source: &str) -> Result<(), String> {
+        validate_vertex_attrs(&self.attrs, vertex_source)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -183,12 +313,20 @@ pub enum BufferUsage {
     Vertex,
     Index,
     Uniform(u32),
+    Storage(u32),
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct VertexAttr {
     pub location: u32,
     pub format: VertexFormat,
+    /// Vertex buffer slot the attribute is read from
+    pub slot: u32,
+    /// Byte offset in the vertex, `None` places it after the previous attribute
+    pub offset: Option<u32>,
+    /// Name of the shader input bound to `location` before linking,
+    /// for shaders without `layout(location = N)` qualifiers
+    pub name: Option<&'static str>,
 }
 
 impl VertexAttr {
@@ -196,14 +334,54 @@ impl VertexAttr {
         Self {
             location,
             format: vertex_data,
+            slot: 0,
+            offset: None,
+            name: None,
         }
     }
+
+    pub fn with_slot(mut self, slot: u32) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+}
+
+/// Byte stride of the attributes of one vertex buffer,
+/// checking that all of them fit in the stride when it's set
+pub(crate) fn vertex_stride(attrs: &[VertexAttr], stride: Option<u32>) -> Result<u32, String> {
+    let mut offset = 0;
+    let mut end = 0;
+    for attr in attrs {
+        offset = attr.offset.unwrap_or(offset) + attr.format.bytes() as u32;
+        end = end.max(offset);
+
+        if let Some(stride) = stride.filter(|stride| offset > *stride) {
+            return Err(format!(
+                "Vertex attribute at location {} ends at byte {}, outside of the stride {}",
+                attr.location, offset, stride
+            ));
+        }
+    }
+
+    Ok(stride.unwrap_or(end))
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum VertexStepMode {
     Vertex,
     Instance,
+    /// Advance every N instances, e.g. one color shared by groups of N instances
+    InstanceEvery(u32),
 }
 
 impl Default for VertexStepMode {
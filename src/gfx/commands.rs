@@ -1,6 +1,8 @@
 use super::{
     color::Color,
-    pipeline::{DrawPrimitive, PipelineOptions},
+    limits::Limits,
+    pipeline::{CompareMode, DrawPrimitive, PipelineOptions},
+    texture::{ImageAccess, TextureFormat, MAX_TEXTURE_SLOTS},
 };
 
 #[allow(unused)]
@@ -18,6 +20,7 @@ pub enum Commands {
     },
     Begin {
         color: Option<Color>,
+        colors: Vec<Color>,
         depth: Option<f32>,
         stencil: Option<i32>,
     },
@@ -26,13 +29,34 @@ pub enum Commands {
         id: u64,
         options: PipelineOptions,
     },
+    ComputePipeline {
+        id: u64,
+    },
     BindBuffer {
         id: u64,
     },
+    /// Bytes `offset..offset + size` of a uniform buffer at the binding `slot`
+    BindUniformRange {
+        id: u64,
+        slot: u32,
+        offset: u32,
+        size: u32,
+    },
     BindTexture {
         id: u64,
         slot: u32,
         location: u32,
+        sampler: Option<u64>,
+    },
+    BindImageTexture {
+        id: u64,
+        unit: u32,
+        access: ImageAccess,
+        format: TextureFormat,
+    },
+    /// List of (id, slot, location)
+    BindTextures {
+        textures: Vec<(u64, u32, u32)>,
     },
     Scissors {
         x: f32,
@@ -51,4 +75,281 @@ pub enum Commands {
         count: i32,
         length: i32,
     },
+    DrawRangeElements {
+        primitive: DrawPrimitive,
+        start: u32,
+        end: u32,
+        offset: i32,
+        count: i32,
+    },
+    /// Indexed draw adding `base_vertex` to each index read
+    DrawBaseVertex {
+        primitive: DrawPrimitive,
+        offset: i32,
+        count: i32,
+        base_vertex: i32,
+    },
+    Dispatch {
+        x: u32,
+        y: u32,
+        z: u32,
+    },
+    PrimitiveRestart {
+        enabled: bool,
+    },
+    DepthCompare {
+        compare: CompareMode,
+    },
+    DepthWrite {
+        enabled: bool,
+    },
+    DrawBuffers {
+        buffers: Vec<Option<u32>>,
+    },
+    StencilReference {
+        reference: u32,
+    },
+    BeginTransformFeedback {
+        primitive: DrawPrimitive,
+        buffer: u64,
+    },
+    EndTransformFeedback,
+    Uniform {
+        location: u32,
+        value: UniformValue,
+    },
+}
+
+/// Value set to a uniform outside of the uniform blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    F32(f32),
+    Vec4([f32; 4]),
+    /// Column-major matrix
+    Mat4([f32; 16]),
+}
+
+/// Check that the draws are inside a pass, and that every `Begin` is closed by an `End`
+pub(crate) fn validate_commands(commands: &[Commands]) -> Result<(), String> {
+    let mut in_pass = false;
+
+    for (i, cmd) in commands.iter().enumerate() {
+        match cmd {
+            Commands::Begin { .. } if in_pass => {
+                return Err(format!("Command {}: Begin called before End", i));
+            }
+            Commands::Begin { .. } => in_pass = true,
+            Commands::End if !in_pass => {
+                return Err(format!("Command {}: End called without Begin", i));
+            }
+            Commands::End => in_pass = false,
+            Commands::Draw { .. }
+            | Commands::DrawInstanced { .. }
+            | Commands::DrawRangeElements { .. }
+            | Commands::DrawBaseVertex { .. }
+                if !in_pass =>
+            {
+                return Err(format!("Command {}: Draw called without Begin", i));
+            }
+            _ => {}
+        }
+    }
+
+    if in_pass {
+        return Err("Begin is not closed by End".to_string());
+    }
+
+    Ok(())
+}
+
+/// Check that the commands only use the features supported by the device
+pub(crate) fn validate_limits(commands: &[Commands], limits: &Limits) -> Result<(), String> {
+    let max_slots = limits.max_texture_image_units.min(MAX_TEXTURE_SLOTS);
+    let check_slot = |i: usize, slot: u32| {
+        if slot >= max_slots {
+            return Err(format!(
+                "Command {}: Texture slot '{}' exceeds the {} texture units of the device",
+                i, slot, max_slots
+            ));
+        }
+        Ok(())
+    };
+
+    commands
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, cmd)| match cmd {
+            Commands::DrawBaseVertex { .. } if !limits.base_vertex => Err(format!(
+                "Command {}: DrawBaseVertex needs GLES 3.2 or OES_draw_elements_base_vertex",
+                i
+            )),
+            Commands::BindTexture { slot, .. } => check_slot(i, *slot),
+            Commands::BindTextures { textures } => textures
+                .iter()
+                .try_for_each(|(_, slot, _)| check_slot(i, *slot)),
+            Commands::BindUniformRange { offset, size, .. } => {
+                check_uniform_range(i, *offset, *size, limits)
+            }
+            Commands::BindImageTexture { unit, format, .. } => {
+                check_image_texture(i, *unit, format, limits)
+            }
+            Commands::DrawBuffers { buffers } => check_draw_buffers(i, buffers),
+            _ => Ok(()),
+        })
+}
+
+fn check_uniform_range(i: usize, offset: u32, size: u32, limits: &Limits) -> Result<(), String> {
+    let alignment = limits.uniform_buffer_offset_alignment.max(1);
+    if !offset.is_multiple_of(alignment) {
+        return Err(format!(
+            "Command {}: Uniform range offset {} is not a multiple of the alignment {}",
+            i, offset, alignment
+        ));
+    }
+
+    if size == 0 {
+        return Err(format!("Command {}: Uniform range of 0 bytes", i));
+    }
+
+    Ok(())
+}
+
+fn check_image_texture(
+    i: usize,
+    unit: u32,
+    format: &TextureFormat,
+    limits: &Limits,
+) -> Result<(), String> {
+    if !limits.image_load_store {
+        return Err(format!(
+            "Command {}: Image load and store is not supported",
+            i
+        ));
+    }
+
+    if unit >= limits.max_image_units {
+        return Err(format!(
+            "Command {}: Image unit {} is out of the {} units",
+            i, unit, limits.max_image_units
+        ));
+    }
+
+    if !format.is_image_storable() {
+        return Err(format!(
+            "Command {}: {:?} can't be used as an image format",
+            i, format
+        ));
+    }
+
+    Ok(())
+}
+
+/// GLES only lets the output `i` write to the attachment `i` or to none
+fn check_draw_buffers(i: usize, buffers: &[Option<u32>]) -> Result<(), String> {
+    buffers
+        .iter()
+        .enumerate()
+        .try_for_each(|(index, buffer)| match buffer {
+            Some(n) if *n as usize != index => Err(format!(
+                "Command {}: Draw buffer '{}' must be the attachment {} or None",
+                i, n, index
+            )),
+            _ => Ok(()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_vertex_draws_need_the_limit() {
+        let commands = [Commands::DrawBaseVertex {
+            primitive: DrawPrimitive::Triangles,
+            offset: 0,
+            count: 3,
+            base_vertex: 4,
+        }];
+
+        let mut limits = Limits {
+            base_vertex: false,
+            ..Default::default()
+        };
+        assert!(validate_limits(&commands, &limits).is_err());
+
+        limits.base_vertex = true;
+        assert!(validate_limits(&commands, &limits).is_ok());
+    }
+
+    #[test]
+    fn texture_slots_need_the_texture_units() {
+        let limits = Limits {
+            max_texture_image_units: 4,
+            ..Default::default()
+        };
+        let bind = |slot| Commands::BindTexture {
+            id: 1,
+            slot,
+            location: 0,
+            sampler: None,
+        };
+        assert!(validate_limits(&[bind(3)], &limits).is_ok());
+        assert!(validate_limits(&[bind(4)], &limits).is_err());
+
+        let group = Commands::BindTextures {
+            textures: vec![(1, 0, 0), (2, 4, 1)],
+        };
+        assert!(validate_limits(&[group], &limits).is_err());
+    }
+
+    #[test]
+    fn uniform_ranges_need_the_alignment() {
+        let limits = Limits {
+            uniform_buffer_offset_alignment: 256,
+            ..Default::default()
+        };
+        let bind = |offset, size| Commands::BindUniformRange {
+            id: 1,
+            slot: 0,
+            offset,
+            size,
+        };
+        assert!(validate_limits(&[bind(512, 64)], &limits).is_ok());
+        assert!(validate_limits(&[bind(64, 64)], &limits).is_err());
+        assert!(validate_limits(&[bind(0, 0)], &limits).is_err());
+    }
+
+    #[test]
+    fn image_textures_need_the_units_and_format() {
+        let limits = Limits {
+            image_load_store: true,
+            max_image_units: 4,
+            ..Default::default()
+        };
+        let bind = |unit, format| Commands::BindImageTexture {
+            id: 1,
+            unit,
+            access: ImageAccess::Write,
+            format,
+        };
+        assert!(validate_limits(&[bind(3, TextureFormat::Rgba32)], &limits).is_ok());
+        assert!(validate_limits(&[bind(4, TextureFormat::Rgba32)], &limits).is_err());
+        assert!(validate_limits(&[bind(0, TextureFormat::R8)], &limits).is_err());
+
+        let unsupported = Limits {
+            image_load_store: false,
+            ..limits
+        };
+        assert!(validate_limits(&[bind(0, TextureFormat::Rgba32)], &unsupported).is_err());
+    }
+
+    #[test]
+    fn draw_buffers_write_to_their_own_attachment() {
+        let draw_buffers = |buffers: &[Option<u32>]| Commands::DrawBuffers {
+            buffers: buffers.to_vec(),
+        };
+        let limits = Limits::default();
+        assert!(validate_limits(&[draw_buffers(&[Some(0), None])], &limits).is_ok());
+        assert!(validate_limits(&[draw_buffers(&[None, Some(0)])], &limits).is_err());
+    }
 }
@@ -1,19 +1,58 @@
 use super::{
+    barrier::BarrierFlags,
     buffer::{
-        Buffer, BufferUsage, IndexBufferBuilder, UniformBufferBuilder, VertexAttr,
-        VertexBufferBuilder, VertexStepMode,
+        vertex_stride, Buffer, BufferUsage, IndexBufferBuilder, IndexFormat, StorageBufferBuilder,
+        UniformBufferBuilder, VertexAttr, VertexBufferBuilder, VertexStepMode,
     },
-    commands::Commands,
-    encoder::CommandEncoder,
-    limits::Limits,
-    pipeline::{Pipeline, PipelineBuilder, PipelineOptions},
-    render_texture::{RenderTexture, RenderTextureBuilder},
+    commands::{validate_commands, validate_limits, Commands},
+    encoder::{CommandEncoder, CommandEncoderPool},
+    fence::FenceId,
+    graph::TransientPool,
+    limits::{BackendInfo, Limits},
+    pipeline::{
+        AttributeInfo, ComputePipeline, Pipeline, PipelineBuilder, PipelineDesc, PipelineOptions,
+    },
+    query::QueryId,
+    rect::Rect,
+    render_texture::{depth_texture_info, validate_mip_level, RenderTexture, RenderTextureBuilder},
+    sampler::{Sampler, SamplerInfo},
+    stats::FrameStats,
     texture::{
-        Texture, TextureBuilder, TextureInfo, TextureRead, TextureReader, TextureUpdate,
-        TextureUpdater,
+        CubeFace, MipmapHint, Texture, TextureBuilder, TextureFilter, TextureFormat, TextureInfo,
+        TextureKind, TextureRead, TextureReader, TextureUpdate, TextureUpdater,
     },
 };
-use std::sync::{Arc, RwLock};
+use std::{
+    borrow::Cow,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// Full-screen triangle sampling the scaled target, `gl_VertexID` gives the corners
+const UPSCALE_VERT: &str = r#"
+    #version 310 es
+    layout(location = 0) out vec2 v_uv;
+
+    void main() {
+        vec2 pos = vec2(float((gl_VertexID & 1) << 2), float((gl_VertexID & 2) << 1));
+        v_uv = pos * 0.5;
+        gl_Position = vec4(pos - 1.0, 0.0, 1.0);
+    }
+"#;
+
+const UPSCALE_FRAG: &str = r#"
+    #version 310 es
+    precision mediump float;
+
+    layout(location = 0) in vec2 v_uv;
+    layout(location = 0) out vec4 color;
+
+    uniform sampler2D u_scene;
+
+    void main() {
+        color = texture(u_scene, v_uv);
+    }
+"#;
 
 /// Device resource ID, used to know which resource was dropped
 #[derive(Debug)]
@@ -22,6 +61,7 @@ pub enum ResourceId {
     Texture(u64),
     Pipeline(u64),
     RenderTexture(u64),
+    Sampler(u64),
 }
 
 /// Represents a the implementation graphics backend like glow, wgpu or another
@@ -31,20 +71,43 @@ pub trait DeviceBackend {
         Default::default()
     }
 
+    /// Return the version and gpu of the implementation
+    fn info(&self) -> BackendInfo {
+        Default::default()
+    }
+
     /// Create a new pipeline and returns the id
     fn create_pipeline(
         &mut self,
         vertex_source: &[u8],
         fragment_source: &[u8],
         vertex_attrs: &[VertexAttr],
+        feedback_varyings: &[String],
         options: PipelineOptions,
     ) -> Result<u64, String>;
 
+    /// Create a batch of pipelines, letting the driver compile them at the same time
+    fn create_pipelines(&mut self, descs: &[PipelineDesc]) -> Vec<Result<u64, String>> {
+        descs
+            .iter()
+            .map(|desc| {
+                self.create_pipeline(
+                    desc.vertex.as_bytes(),
+                    desc.fragment.as_bytes(),
+                    desc.attrs(),
+                    &[],
+                    desc.options.clone(),
+                )
+            })
+            .collect()
+    }
+
     /// Create a new vertex buffer object and returns the id
     fn create_vertex_buffer(
         &mut self,
         attrs: &[VertexAttr],
         step_mode: VertexStepMode,
+        stride: u32,
     ) -> Result<u64, String>;
 
     /// Create a new index buffer object and returns the id
@@ -53,12 +116,41 @@ pub trait DeviceBackend {
     /// Create a new uniform buffer and returns the id
     fn create_uniform_buffer(&mut self, slot: u32, name: &str) -> Result<u64, String>;
 
+    /// Create a new shader storage buffer and returns the id
+    fn create_storage_buffer(&mut self, slot: u32, name: &str) -> Result<u64, String>;
+
+    /// Create a new sampler object and returns the id
+    fn create_sampler(&mut self, info: &SamplerInfo) -> Result<u64, String>;
+
+    /// Create a new compute pipeline and returns the id
+    fn create_compute_pipeline(&mut self, source: &[u8]) -> Result<u64, String>;
+
     /// Upload to the GPU the buffer data slice
     fn set_buffer_data(&mut self, buffer: u64, data: &[u8]);
 
+    /// Set the type of the indices read from an index buffer by the indexed draws
+    fn set_index_format(&mut self, buffer: u64, format: IndexFormat);
+
+    /// Upload to the GPU the buffer data slice into a new storage of the buffer
+    fn set_buffer_data_orphaned(&mut self, buffer: u64, data: &[u8]);
+
+    /// Upload to the GPU a slice of data at the byte offset of an already allocated buffer
+    fn set_buffer_sub_data(
+        &mut self,
+        buffer: u64,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), String>;
+
     /// Create a new renderer using the size of the graphics
     fn render(&mut self, commands: &[Commands], target: Option<u64>);
 
+    /// Ids of all the textures alive, except the attachments of the render textures
+    fn texture_ids(&self) -> Vec<u64>;
+
+    /// Ids of all the graphics and compute pipelines alive
+    fn pipeline_ids(&self) -> Vec<u64>;
+
     /// Clean all the dropped resources
     fn clean(&mut self, to_clean: &[ResourceId]);
 
@@ -68,12 +160,53 @@ pub trait DeviceBackend {
     /// Sets the screen dpi
     fn set_dpi(&mut self, scale_factor: f64);
 
+    /// Quality of the mipmaps generated from now on
+    fn set_mipmap_hint(&mut self, hint: MipmapHint);
+
+    /// Viewport and scissors applied to the passes rendered to the screen, `None` uses all of it
+    fn set_letterbox(&mut self, rect: Option<Rect>);
+
     /// Create a new texture and returns the id
     fn create_texture(&mut self, info: &TextureInfo) -> Result<u64, String>;
 
     /// Create a new render target and returns the id
-    fn create_render_texture(&mut self, texture_id: u64, info: &TextureInfo)
-        -> Result<u64, String>;
+    fn create_render_texture(
+        &mut self,
+        texture_id: u64,
+        depth_texture_id: Option<u64>,
+        info: &TextureInfo,
+    ) -> Result<u64, String>;
+
+    /// Recreate the render target and its textures using the same ids
+    fn resize_render_texture(
+        &mut self,
+        render_texture: u64,
+        texture_id: u64,
+        depth_texture_id: Option<u64>,
+        info: &TextureInfo,
+    ) -> Result<(), String>;
+
+    /// Active vertex attributes of the pipeline
+    fn pipeline_attributes(&self, pipeline: u64) -> Vec<AttributeInfo>;
+
+    /// Check if textures of the format can be rendered to
+    fn is_format_renderable(&mut self, format: TextureFormat) -> bool;
+
+    /// Copy the color texture of a render target into a texture of the same size
+    fn copy_render_texture_to_texture(&mut self, src: u64, dst: u64) -> Result<(), String>;
+
+    /// Attach this face of the cubemap of a render target as its color attachment
+    fn set_render_texture_face(&mut self, id: u64, face: CubeFace) -> Result<(), String>;
+
+    /// Copy a region of a render target into another one
+    fn blit(
+        &mut self,
+        src: u64,
+        dst: u64,
+        src_rect: &Rect,
+        dst_rect: &Rect,
+        filter: &TextureFilter,
+    ) -> Result<(), String>;
 
     /// Update texture data
     fn update_texture(&mut self, texture: u64, opts: &TextureUpdate) -> Result<(), String>;
@@ -86,8 +219,50 @@ pub trait DeviceBackend {
         opts: &TextureRead,
     ) -> Result<(), String>;
 
+    /// Read RGBA pixels from the default framebuffer
+    fn read_screen(&mut self, x: i32, y: i32, width: i32, height: i32, bytes: &mut [u8]);
+
     /// Let the backend swap the window buffer
     fn swap_buffers(&mut self);
+
+    /// Submit the rendering done to the render targets before sampling them
+    fn barrier(&mut self);
+
+    /// Make the shader writes done so far visible to the reads described by the flags
+    fn memory_barrier(&mut self, flags: BarrierFlags);
+
+    /// Counters of the last frame finished by `swap_buffers`
+    fn stats(&self) -> FrameStats;
+
+    /// Start an occlusion query and returns the id
+    fn begin_occlusion_query(&mut self) -> Result<u64, String>;
+
+    /// Stop counting samples for the query
+    fn end_occlusion_query(&mut self, query: u64);
+
+    /// Returns if any sample passed the tests, `None` if the result is not available yet
+    fn occlusion_result(&mut self, query: u64) -> Option<bool>;
+
+    /// Delete the query from the gpu
+    fn delete_occlusion_query(&mut self, query: u64);
+
+    /// Insert a fence after the commands sent so far and returns the id
+    fn insert_fence(&mut self) -> Result<u64, String>;
+
+    /// Block until the fence is signaled or the timeout expires, returns if it was signaled
+    fn wait_fence(&mut self, fence: u64, timeout: Duration) -> bool;
+
+    /// Returns if the gpu reached the fence without blocking
+    fn is_fence_signaled(&mut self, fence: u64) -> bool;
+
+    /// Delete the fence from the gpu
+    fn delete_fence(&mut self, fence: u64);
+
+    /// Disable the blend, depth, stencil, cull and scissor tests and reset the color mask
+    fn reset_gl_state(&mut self);
+
+    /// Reset the gl state automatically at the end of each render pass
+    fn set_reset_gl_state_on_end(&mut self, enable: bool);
 }
 
 /// Helper to drop resources on the backend
@@ -112,6 +287,17 @@ pub struct Device<B: DeviceBackend> {
     dpi: f64,
     backend: B,
     drop_manager: Arc<DropManager>,
+    encoder_pool: CommandEncoderPool,
+    transient_pool: TransientPool,
+    letterbox: Option<(f32, f32)>,
+    render_scale: f32,
+    /// Target of `render` when the render scale is not 1, upscaled on `swap_buffers`
+    scaled_target: Option<RenderTexture>,
+    /// Draws the scaled target to the window, a blit can't write to a multisampled window
+    upscale_pipeline: Option<Pipeline>,
+    /// Encode and submit times of the current frame
+    cpu_times: (Duration, Duration),
+    last_cpu_times: (Duration, Duration),
 }
 
 impl<B: DeviceBackend> Device<B> {
@@ -121,6 +307,14 @@ impl<B: DeviceBackend> Device<B> {
             size: (1, 1),
             dpi: 1.0,
             drop_manager: Arc::new(Default::default()),
+            encoder_pool: Default::default(),
+            transient_pool: Default::default(),
+            letterbox: None,
+            render_scale: 1.0,
+            scaled_target: None,
+            upscale_pipeline: None,
+            cpu_times: Default::default(),
+            last_cpu_times: Default::default(),
         }
     }
 
@@ -129,15 +323,239 @@ impl<B: DeviceBackend> Device<B> {
         self.backend.limits()
     }
 
+    /// Version and gpu of the context, to log the environment or branch on capabilities
+    #[inline]
+    pub fn backend_info(&self) -> BackendInfo {
+        self.backend.info()
+    }
+
     #[inline]
     pub fn size(&self) -> (i32, i32) {
         self.size
     }
 
+    /// Set the size of the window framebuffer, it fails if it's bigger than the viewport limit
     #[inline]
-    pub fn set_size(&mut self, width: i32, height: i32) {
+    pub fn set_size(&mut self, width: i32, height: i32) -> Result<(), String> {
+        let (max_width, max_height) = self.limits().max_viewport_dims;
+        if width as u32 > max_width || height as u32 > max_height {
+            return Err(format!(
+                "Size {}x{} is bigger than the max viewport of {}x{}",
+                width, height, max_width, max_height
+            ));
+        }
+
         self.size = (width, height);
         self.backend.set_size(width, height);
+        self.backend.set_letterbox(self.letterbox_rect());
+        self.update_scaled_target()
+    }
+
+    /// Check the size of a render texture against the texture, renderbuffer and viewport limits
+    fn validate_render_texture_size(&self, width: i32, height: i32) -> Result<(), String> {
+        let limits = self.limits();
+        let max_size = limits.max_texture_size.min(limits.max_renderbuffer_size);
+        let (max_width, max_height) = limits.max_viewport_dims;
+        let (max_width, max_height) = (max_size.min(max_width), max_size.min(max_height));
+        if width <= 0 || height <= 0 || width as u32 > max_width || height as u32 > max_height {
+            return Err(format!(
+                "Render texture of {}x{}, the size must be between 1x1 and {}x{}",
+                width, height, max_width, max_height
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Render the scene passed to `render` at `size * scale` and upscale it to the window
+    /// (or the letterbox) on `swap_buffers`, to lower the cost of the fragments on slow GPUs.
+    /// 1.0 disables it
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), String> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(format!("Invalid render scale {}", scale));
+        }
+
+        // a failed call keeps the previous scale and target
+        if scale != 1.0 {
+            let (width, height) = self.scaled_size(scale);
+            self.validate_render_texture_size(width, height)?;
+        }
+
+        let previous = std::mem::replace(&mut self.render_scale, scale);
+        let result = self.update_scaled_target();
+        if result.is_err() {
+            self.render_scale = previous;
+        }
+
+        result
+    }
+
+    #[inline]
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Size of the target of `render`, the window (or letterbox) size multiplied by the render scale
+    pub fn render_size(&self) -> (i32, i32) {
+        if self.render_scale == 1.0 {
+            return self.size;
+        }
+
+        self.scaled_size(self.render_scale)
+    }
+
+    /// Size of the scaled target for a render scale
+    fn scaled_size(&self, scale: f32) -> (i32, i32) {
+        let area = self.presented_rect();
+        (
+            ((area.width * scale) as i32).max(1),
+            ((area.height * scale) as i32).max(1),
+        )
+    }
+
+    /// Area of the window where the scene is shown, the letterbox or the whole window
+    fn presented_rect(&self) -> Rect {
+        self.letterbox_rect().unwrap_or(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.size.0 as _,
+            height: self.size.1 as _,
+        })
+    }
+
+    /// Move the window pixel viewports and scissors into the scaled target
+    fn scale_commands<'a>(&self, commands: &'a [Commands]) -> Cow<'a, [Commands]> {
+        let needs_scale = commands
+            .iter()
+            .any(|cmd| matches!(cmd, Commands::Viewport { .. } | Commands::Scissors { .. }));
+        if self.scaled_target.is_none() || !needs_scale {
+            return Cow::Borrowed(commands);
+        }
+
+        let area = self.presented_rect();
+        let scale = self.render_scale;
+        let rect = |x: f32, y: f32, width: f32, height: f32| {
+            (
+                (x - area.x) * scale,
+                (y - area.y) * scale,
+                width * scale,
+                height * scale,
+            )
+        };
+
+        Cow::Owned(
+            commands
+                .iter()
+                .map(|cmd| match *cmd {
+                    Commands::Viewport {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } => {
+                        let (x, y, width, height) = rect(x, y, width, height);
+                        Commands::Viewport {
+                            x,
+                            y,
+                            width,
+                            height,
+                        }
+                    }
+                    Commands::Scissors {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } => {
+                        let (x, y, width, height) = rect(x, y, width, height);
+                        Commands::Scissors {
+                            x,
+                            y,
+                            width,
+                            height,
+                        }
+                    }
+                    _ => cmd.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    fn update_scaled_target(&mut self) -> Result<(), String> {
+        if self.render_scale == 1.0 {
+            self.scaled_target = None;
+            self.upscale_pipeline = None;
+            return Ok(());
+        }
+
+        if self.upscale_pipeline.is_none() {
+            let pipeline = self
+                .create_pipeline()
+                .from(UPSCALE_VERT, UPSCALE_FRAG)
+                .build()?;
+            self.upscale_pipeline = Some(pipeline);
+        }
+
+        let (width, height) = self.render_size();
+        match self.scaled_target.take() {
+            Some(target) if target.info().width == width && target.info().height == height => {
+                self.scaled_target = Some(target);
+            }
+            Some(mut target) => {
+                let result = self.resize_render_texture(&mut target, width, height);
+                self.scaled_target = Some(target);
+                result?;
+            }
+            None => {
+                let target = RenderTextureBuilder::new(self, width, height)
+                    .with_depth()
+                    .with_filter(TextureFilter::Linear, TextureFilter::Linear)
+                    .build()?;
+                self.scaled_target = Some(target);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render to the screen in a centered area with the aspect ratio of the logical size,
+    /// the bars around it keep the clear color and can't be drawn
+    pub fn set_letterbox(&mut self, logical_width: f32, logical_height: f32) -> Result<(), String> {
+        self.letterbox = Some((logical_width, logical_height));
+        self.backend.set_letterbox(self.letterbox_rect());
+        self.update_scaled_target()
+    }
+
+    /// Use the whole screen again
+    pub fn clear_letterbox(&mut self) -> Result<(), String> {
+        self.letterbox = None;
+        self.backend.set_letterbox(None);
+        self.update_scaled_target()
+    }
+
+    /// Trade the quality of the generated mipmaps for generation speed,
+    /// it applies to the textures created after the call
+    #[inline]
+    pub fn set_mipmap_hint(&mut self, hint: MipmapHint) {
+        self.backend.set_mipmap_hint(hint);
+    }
+
+    /// Area of the screen used by the letterbox
+    pub fn letterbox_rect(&self) -> Option<Rect> {
+        let (logical_width, logical_height) = self.letterbox?;
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        if logical_width <= 0.0 || logical_height <= 0.0 {
+            return None;
+        }
+
+        let scale = (width / logical_width).min(height / logical_height);
+        let (w, h) = (logical_width * scale, logical_height * scale);
+        Some(Rect {
+            x: ((width - w) * 0.5).floor(),
+            y: ((height - h) * 0.5).floor(),
+            width: w.floor(),
+            height: h.floor(),
+        })
     }
 
     #[inline]
@@ -156,11 +574,68 @@ impl<B: DeviceBackend> Device<B> {
         CommandEncoder::new(self.size.0, self.size.1)
     }
 
+    /// Pool to reuse the encoders between frames instead of creating new ones
+    #[inline]
+    pub fn command_encoder_pool(&mut self) -> &mut CommandEncoderPool {
+        &mut self.encoder_pool
+    }
+
     #[inline]
     pub fn create_pipeline(&mut self) -> PipelineBuilder<B> {
         PipelineBuilder::new(self)
     }
 
+    /// Compile and link a batch of pipelines up front, to avoid the stall of compiling them
+    /// on their first use. The shaders are all sent before checking any of them, so the driver
+    /// can compile them in parallel, on its own threads with `KHR_parallel_shader_compile`
+    pub fn precompile_pipelines(
+        &mut self,
+        descs: &[PipelineDesc],
+    ) -> Result<Vec<Pipeline>, String> {
+        let strides = descs
+            .iter()
+            .map(|desc| {
+                let slot_attrs = desc
+                    .attrs()
+                    .iter()
+                    .filter(|attr| attr.slot == 0)
+                    .copied()
+                    .collect::<Vec<_>>();
+                let stride = desc.vertex_info.and_then(|info| info.stride);
+                vertex_stride(&slot_attrs, stride)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let ids = self.backend.create_pipelines(descs);
+
+        // every created id is wrapped first, so the pipelines before and after a failed one
+        // are dropped through the drop manager instead of leaking in the backend
+        let pipelines = descs
+            .iter()
+            .zip(strides)
+            .zip(ids)
+            .map(|((desc, stride), id)| {
+                let id = id?;
+                Ok(Pipeline::new(
+                    id,
+                    stride as _,
+                    self.backend.pipeline_attributes(id),
+                    desc.options.clone(),
+                    self.drop_manager.clone(),
+                ))
+            })
+            .collect::<Vec<Result<Pipeline, String>>>();
+
+        pipelines.into_iter().collect()
+    }
+
+    /// Pipeline builder for a pipeline capturing the varyings with transform feedback,
+    /// they need to be known before the program is linked
+    #[inline]
+    pub fn create_transform_feedback(&mut self, varyings: &[&str]) -> PipelineBuilder<'_, '_, B> {
+        PipelineBuilder::new(self).with_transform_feedback(varyings)
+    }
+
     #[inline]
     pub fn create_texture(&mut self) -> TextureBuilder<B> {
         TextureBuilder::new(self)
@@ -186,6 +661,33 @@ impl<B: DeviceBackend> Device<B> {
         UniformBufferBuilder::new(self, slot, name)
     }
 
+    #[inline]
+    pub fn create_storage_buffer(&mut self, slot: u32, name: &str) -> StorageBufferBuilder<'_, B> {
+        StorageBufferBuilder::new(self, slot, name)
+    }
+
+    /// Compile a `#version 310 es` compute shader
+    #[inline]
+    pub fn create_compute_pipeline(&mut self, source: &str) -> Result<ComputePipeline, String> {
+        let id = self.backend.create_compute_pipeline(source.as_bytes())?;
+        Ok(ComputePipeline::new(id, self.drop_manager.clone()))
+    }
+
+    /// Create a sampler to bind with `CommandEncoder::bind_texture_sampler`,
+    /// the same texture can be sampled with different filters in each pass
+    #[inline]
+    pub fn create_sampler(&mut self, info: SamplerInfo) -> Result<Sampler, String> {
+        let id = self.backend.create_sampler(&info)?;
+        Ok(Sampler::new(id, info, self.drop_manager.clone()))
+    }
+
+    /// Check if a render texture can use the format on this gpu (float formats usually
+    /// need an extension), instead of failing with an incomplete framebuffer
+    #[inline]
+    pub fn is_format_renderable(&mut self, format: TextureFormat) -> bool {
+        self.backend.is_format_renderable(format)
+    }
+
     #[inline]
     pub fn update_texture<'a>(&'a mut self, texture: &'a mut Texture) -> TextureUpdater<B> {
         TextureUpdater::new(self, texture)
@@ -196,9 +698,155 @@ impl<B: DeviceBackend> Device<B> {
         TextureReader::new(self, texture)
     }
 
+    /// Read the RGBA pixels of the window backbuffer, the rows are returned top to bottom
+    pub fn read_screen(&mut self, x: i32, y: i32, width: i32, height: i32) -> Vec<u8> {
+        let row_len = width.max(0) as usize * 4;
+        let rows = height.max(0) as usize;
+        let mut bytes = vec![0; row_len * rows];
+        if bytes.is_empty() {
+            return bytes;
+        }
+
+        self.backend.read_screen(x, y, width, height, &mut bytes);
+
+        // gl returns the bottom row first
+        let mid = rows / 2;
+        let (top, bottom) = bytes.split_at_mut(mid * row_len);
+        for i in 0..mid {
+            let j = rows - mid - 1 - i;
+            top[i * row_len..(i + 1) * row_len]
+                .swap_with_slice(&mut bottom[j * row_len..(j + 1) * row_len]);
+        }
+
+        bytes
+    }
+
     #[inline]
     pub fn swap_buffers(&mut self) {
+        // the pass to the window keeps out of the letterbox bars like any other
+        if let (Some(target), Some(pipeline)) = (&self.scaled_target, &self.upscale_pipeline) {
+            let mut encoder = self.encoder_pool.acquire(self.size.0, self.size.1);
+            encoder.begin(None);
+            encoder.set_pipeline(pipeline);
+            encoder.bind_texture(0, target.texture());
+            encoder.draw(0, 3);
+            encoder.end();
+            self.backend.render(encoder.commands(), None);
+            self.encoder_pool.release(encoder);
+        }
+
         self.backend.swap_buffers();
+        self.transient_pool.end_frame();
+        self.last_cpu_times = std::mem::take(&mut self.cpu_times);
+    }
+
+    /// Render texture for intermediate passes, taken from a pool. The storage is reused
+    /// for later passes of the same size and format once the returned handle (and its
+    /// textures) are dropped, and released if it isn't used for a whole frame
+    pub fn transient_render_texture(
+        &mut self,
+        width: i32,
+        height: i32,
+        format: TextureFormat,
+    ) -> Result<RenderTexture, String> {
+        if let Some(render_texture) = self.transient_pool.acquire(width, height, format) {
+            return Ok(render_texture);
+        }
+
+        let render_texture = RenderTextureBuilder::new(self, width, height)
+            .with_format(format)
+            .build()?;
+        Ok(self.transient_pool.insert(render_texture))
+    }
+
+    /// Submit the rendering done by `render_to` before using the render textures
+    #[inline]
+    pub fn barrier(&mut self) {
+        self.backend.barrier();
+    }
+
+    /// Wait for the storage buffer and image writes of the previous compute or draw calls
+    /// before the reads described by the flags, like vertex fetch or texture sampling
+    #[inline]
+    pub fn memory_barrier(&mut self, flags: BarrierFlags) {
+        if !flags.is_empty() {
+            self.backend.memory_barrier(flags);
+        }
+    }
+
+    /// Draw calls, triangles, buffer uploads and CPU times of the last frame
+    #[inline]
+    pub fn stats(&self) -> FrameStats {
+        let (encode_time, submit_time) = self.last_cpu_times;
+        FrameStats {
+            encode_time,
+            submit_time,
+            ..self.backend.stats()
+        }
+    }
+
+    /// Start counting the samples that pass the depth and stencil tests for the draws
+    /// rendered until `end_occlusion_query`.
+    /// Rendering a cheap bounding box proxy inside the query allows to skip an expensive draw
+    /// on the next frames if no sample passed
+    #[inline]
+    pub fn begin_occlusion_query(&mut self) -> Result<QueryId, String> {
+        if !self.limits().occlusion_query {
+            return Err("Occlusion queries are not supported by this device".to_string());
+        }
+
+        self.backend.begin_occlusion_query().map(QueryId)
+    }
+
+    #[inline]
+    pub fn end_occlusion_query(&mut self, query: QueryId) {
+        self.backend.end_occlusion_query(query.id());
+    }
+
+    /// Returns if any sample passed, `None` while the gpu is still working on it
+    #[inline]
+    pub fn occlusion_result(&mut self, query: QueryId) -> Option<bool> {
+        self.backend.occlusion_result(query.id())
+    }
+
+    #[inline]
+    pub fn delete_occlusion_query(&mut self, query: QueryId) {
+        self.backend.delete_occlusion_query(query.id());
+    }
+
+    /// Mark the point reached by the commands sent so far, used to know when the gpu
+    /// is done reading a buffer before writing it again (multi-buffering the instance data)
+    #[inline]
+    pub fn insert_fence(&mut self) -> Result<FenceId, String> {
+        self.backend.insert_fence().map(FenceId)
+    }
+
+    /// Block until the gpu reaches the fence, returns `false` if the timeout expired first
+    #[inline]
+    pub fn wait_fence(&mut self, fence: FenceId, timeout: Duration) -> bool {
+        self.backend.wait_fence(fence.id(), timeout)
+    }
+
+    #[inline]
+    pub fn is_fence_signaled(&mut self, fence: FenceId) -> bool {
+        self.backend.is_fence_signaled(fence.id())
+    }
+
+    #[inline]
+    pub fn delete_fence(&mut self, fence: FenceId) {
+        self.backend.delete_fence(fence.id());
+    }
+
+    /// Leave the context in a clean state for other renderers sharing it (like egui)
+    #[inline]
+    pub fn reset_gl_state(&mut self) {
+        self.backend.reset_gl_state();
+    }
+
+    /// Call `reset_gl_state` automatically at the end of each render pass
+    #[inline]
+    pub fn set_reset_gl_state_on_end(&mut self, enable: bool) {
+        self.backend.set_reset_gl_state_on_end(enable);
     }
 
     #[inline]
@@ -207,22 +855,32 @@ impl<B: DeviceBackend> Device<B> {
         vertex_source: &[u8],
         fragment_source: &[u8],
         vertex_attrs: &[VertexAttr],
+        stride: Option<u32>,
+        feedback_varyings: &[String],
         options: PipelineOptions,
     ) -> Result<Pipeline, String> {
-        let stride = vertex_attrs
+        // stride of the first vertex buffer, the other slots have their own layout
+        let slot_attrs = vertex_attrs
             .iter()
-            .fold(0, |acc, data| acc + data.format.bytes()) as usize;
+            .filter(|data| data.slot == 0)
+            .copied()
+            .collect::<Vec<_>>();
+        let stride = vertex_stride(&slot_attrs, stride)? as usize;
 
         let id = self.backend.create_pipeline(
             vertex_source,
             fragment_source,
             vertex_attrs,
+            feedback_varyings,
             options.clone(),
         )?;
 
+        let attributes = self.backend.pipeline_attributes(id);
+
         Ok(Pipeline::new(
             id,
             stride,
+            attributes,
             options,
             self.drop_manager.clone(),
         ))
@@ -234,11 +892,20 @@ impl<B: DeviceBackend> Device<B> {
         vertex_source: &str,
         fragment_source: &str,
         vertex_attrs: &[VertexAttr],
+        stride: Option<u32>,
+        feedback_varyings: &[String],
         options: PipelineOptions,
     ) -> Result<Pipeline, String> {
         let vertex = vertex_source.as_bytes();
         let fragment = fragment_source.as_bytes();
-        self.inner_create_pipeline_from_raw(vertex, fragment, vertex_attrs, options)
+        self.inner_create_pipeline_from_raw(
+            vertex,
+            fragment,
+            vertex_attrs,
+            stride,
+            feedback_varyings,
+            options,
+        )
     }
 
     #[inline(always)]
@@ -247,8 +914,15 @@ impl<B: DeviceBackend> Device<B> {
         data: Option<&[f32]>,
         attrs: &[VertexAttr],
         step_mode: VertexStepMode,
+        stride: u32,
     ) -> Result<Buffer, String> {
-        let id = self.backend.create_vertex_buffer(attrs, step_mode)?;
+        if let VertexStepMode::InstanceEvery(0) = step_mode {
+            return Err("VertexStepMode::InstanceEvery needs a divisor greater than 0".to_string());
+        }
+
+        let id = self
+            .backend
+            .create_vertex_buffer(attrs, step_mode, stride)?;
 
         let buffer = Buffer::new(id, BufferUsage::Vertex, None, self.drop_manager.clone());
 
@@ -296,8 +970,49 @@ impl<B: DeviceBackend> Device<B> {
         Ok(buffer)
     }
 
+    #[inline]
+    pub(crate) fn inner_create_storage_buffer(
+        &mut self,
+        slot: u32,
+        name: &str,
+        data: Option<&[f32]>,
+    ) -> Result<Buffer, String> {
+        let id = self.backend.create_storage_buffer(slot, name)?;
+        let buffer = Buffer::new(
+            id,
+            BufferUsage::Storage(slot),
+            None,
+            self.drop_manager.clone(),
+        );
+
+        if let Some(d) = data {
+            self.set_buffer_data(&buffer, d);
+        }
+
+        Ok(buffer)
+    }
+
     #[inline]
     pub(crate) fn inner_create_texture(&mut self, info: TextureInfo) -> Result<Texture, String> {
+        match info.kind {
+            TextureKind::Array { layers } => {
+                let max_layers = self.limits().max_array_texture_layers;
+                if layers == 0 || layers > max_layers {
+                    return Err(format!(
+                        "Texture array with {} layers, it must have between 1 and {}",
+                        layers, max_layers
+                    ));
+                }
+            }
+            TextureKind::Cube if info.width != info.height => {
+                return Err(format!(
+                    "Cubemap faces must be square, not {}x{}",
+                    info.width, info.height
+                ));
+            }
+            _ => {}
+        }
+
         let id = self.backend.create_texture(&info)?;
         Ok(Texture::new(id, info, self.drop_manager.clone()))
     }
@@ -305,23 +1020,205 @@ impl<B: DeviceBackend> Device<B> {
     #[inline]
     pub(crate) fn inner_create_render_texture(
         &mut self,
-        info: TextureInfo,
+        mut info: TextureInfo,
     ) -> Result<RenderTexture, String> {
+        self.validate_render_texture_size(info.width, info.height)?;
+
+        if info.depth && !self.is_format_renderable(info.depth_format) {
+            eprintln!(
+                "Depth format {:?} is not renderable, using Depth16 instead",
+                info.depth_format
+            );
+            info.depth_format = TextureFormat::Depth16;
+        }
+
         let tex_id = self.backend.create_texture(&info)?;
+        let texture = Texture::new(tex_id, info.clone(), self.drop_manager.clone());
+
+        let depth_texture = match depth_texture_info(&info) {
+            Some(depth_info) => {
+                let depth_id = self.backend.create_texture(&depth_info)?;
+                Some(Texture::new(
+                    depth_id,
+                    depth_info,
+                    self.drop_manager.clone(),
+                ))
+            }
+            None => None,
+        };
+
+        let id = self.backend.create_render_texture(
+            tex_id,
+            depth_texture.as_ref().map(|t| t.id()),
+            &info,
+        )?;
 
-        let id = self.backend.create_render_texture(tex_id, &info)?;
-        let texture = Texture::new(tex_id, info, self.drop_manager.clone());
-        Ok(RenderTexture::new(id, texture, self.drop_manager.clone()))
+        Ok(RenderTexture::new(
+            id,
+            texture,
+            depth_texture,
+            info,
+            self.drop_manager.clone(),
+        ))
     }
 
+    /// Copy `src_rect` of `src` into `dst_rect` of `dst`, scaling it with `filter`
+    /// if the sizes are different. Both render textures must use the same format.
+    pub fn blit(
+        &mut self,
+        src: &RenderTexture,
+        dst: &RenderTexture,
+        src_rect: Rect,
+        dst_rect: Rect,
+        filter: TextureFilter,
+    ) -> Result<(), String> {
+        let (src_format, dst_format) = (src.texture().format(), dst.texture().format());
+        if src_format != dst_format {
+            return Err(format!(
+                "Cannot blit a {:?} render texture into a {:?} one",
+                src_format, dst_format
+            ));
+        }
+
+        self.backend
+            .blit(src.id(), dst.id(), &src_rect, &dst_rect, &filter)
+    }
+
+    /// Copy the content of the render texture into `dst` on the GPU,
+    /// to keep a snapshot while the render texture is drawn again
+    pub fn copy_render_texture_to_texture(
+        &mut self,
+        src: &RenderTexture,
+        dst: &mut Texture,
+    ) -> Result<(), String> {
+        let (src_format, dst_format) = (src.texture().format(), dst.format());
+        if src_format != dst_format {
+            return Err(format!(
+                "Cannot copy a {:?} render texture into a {:?} texture",
+                src_format, dst_format
+            ));
+        }
+
+        let (src_size, dst_size) = (src.texture().size(), dst.size());
+        if src_size != dst_size {
+            return Err(format!(
+                "Cannot copy a render texture of size {:?} into a texture of size {:?}",
+                src_size, dst_size
+            ));
+        }
+
+        self.backend
+            .copy_render_texture_to_texture(src.id(), dst.id())
+    }
+
+    /// Recreate the texture and framebuffer of the render texture with a new size,
+    /// keeping the same ids so the commands already using it stay valid
+    pub fn resize_render_texture(
+        &mut self,
+        render_texture: &mut RenderTexture,
+        width: i32,
+        height: i32,
+    ) -> Result<(), String> {
+        let info = TextureInfo {
+            width,
+            height,
+            ..render_texture.info().clone()
+        };
+
+        validate_mip_level(&info)?;
+        self.validate_render_texture_size(width, height)?;
+
+        self.backend.resize_render_texture(
+            render_texture.id(),
+            render_texture.texture().id(),
+            render_texture.depth_texture().map(|t| t.id()),
+            &info,
+        )?;
+
+        render_texture.set_size(width, height);
+        Ok(())
+    }
+
+    /// Render to the screen, or to the scaled target when a render scale is set.
+    ///
+    /// `Viewport` and `Scissors` use window pixels in both cases, they are moved into
+    /// the scaled target (relative to the letterbox when there is one).
+    /// Fails without rendering anything if a command needs a feature missing from `limits`
+    #[inline]
+    pub fn render(&mut self, commands: &[Commands]) -> Result<(), String> {
+        debug_validate_commands(commands);
+        validate_limits(commands, &self.limits())?;
+        let target = self.scaled_target.as_ref().map(|target| target.id());
+        let commands = self.scale_commands(commands);
+        let start = Instant::now();
+        self.backend.render(&commands, target);
+        self.cpu_times.1 += start.elapsed();
+        Ok(())
+    }
+
+    /// Render the commands of the encoder like `render`, adding its encoding time to the stats
     #[inline]
-    pub fn render(&mut self, commands: &[Commands]) {
-        self.backend.render(commands, None);
+    pub fn submit(&mut self, encoder: &CommandEncoder) -> Result<(), String> {
+        self.cpu_times.0 += encoder.encode_duration();
+        self.render(encoder.commands())
     }
 
+    /// Render to the texture of `target`.
+    ///
+    /// On tiled mobile GPUs sampling the texture before the rendering is submitted can
+    /// return stale data, so the backend flushes when the texture is bound later in a
+    /// `render`. Use `barrier` when the texture is read some other way (blit, read pixels...)
     #[inline]
-    pub fn render_to(&mut self, target: &RenderTexture, commands: &[Commands]) {
+    pub fn render_to(
+        &mut self,
+        target: &RenderTexture,
+        commands: &[Commands],
+    ) -> Result<(), String> {
+        debug_validate_commands(commands);
+        validate_limits(commands, &self.limits())?;
+        let start = Instant::now();
         self.backend.render(commands, Some(target.id()));
+        self.cpu_times.1 += start.elapsed();
+        Ok(())
+    }
+
+    /// Render the commands of the encoder like `render_to`, adding its encoding time to the stats
+    #[inline]
+    pub fn submit_to(
+        &mut self,
+        target: &RenderTexture,
+        encoder: &CommandEncoder,
+    ) -> Result<(), String> {
+        self.cpu_times.0 += encoder.encode_duration();
+        self.render_to(target, encoder.commands())
+    }
+
+    /// Render the faces of a cubemap render texture, one pass per face.
+    ///
+    /// `f` records the commands of each face on the encoder, the column-major view matrix looks
+    /// from the origin so it must be combined with the translation of the eye and a 90 degrees
+    /// projection. The faces are attached one after another, layered rendering needs
+    /// geometry shaders that GLES doesn't have
+    pub fn render_cubemap<F>(&mut self, target: &RenderTexture, mut f: F) -> Result<(), String>
+    where
+        F: FnMut(CubeFace, [f32; 16], &mut CommandEncoder),
+    {
+        if target.kind() != &TextureKind::Cube {
+            return Err("The render texture wasn't created with a cubemap".to_string());
+        }
+
+        let (width, height) = target.mip_size();
+        for face in CubeFace::ALL {
+            self.backend.set_render_texture_face(target.id(), face)?;
+
+            let mut encoder = self.encoder_pool.acquire(width, height);
+            f(face, face.view_matrix(), &mut encoder);
+            let result = self.submit_to(target, &encoder);
+            self.encoder_pool.release(encoder);
+            result?;
+        }
+
+        Ok(())
     }
 
     #[inline]
@@ -343,6 +1240,24 @@ impl<B: DeviceBackend> Device<B> {
         self.backend.read_pixels(texture.id(), bytes, opts)
     }
 
+    /// Queue every texture to be dropped on the next `clean`, to reset a scene
+    /// without recreating the device. The handles still alive become invalid,
+    /// the textures of the render textures are kept since their framebuffers use them
+    pub fn drop_all_textures(&mut self) {
+        self.backend
+            .texture_ids()
+            .into_iter()
+            .for_each(|id| self.drop_manager.push(ResourceId::Texture(id)));
+    }
+
+    /// Queue every pipeline to be dropped on the next `clean`, the handles still alive become invalid
+    pub fn drop_all_pipelines(&mut self) {
+        self.backend
+            .pipeline_ids()
+            .into_iter()
+            .for_each(|id| self.drop_manager.push(ResourceId::Pipeline(id)));
+    }
+
     #[inline]
     pub fn clean(&mut self) {
         if self.drop_manager.dropped.read().unwrap().is_empty() {
@@ -354,13 +1269,400 @@ impl<B: DeviceBackend> Device<B> {
         self.drop_manager.clean();
     }
 
+    /// The draws rendered before the call keep using the old data and the ones after it
+    /// use the new data, the driver may wait for the pending draws to finish to ensure it.
+    /// Use `set_buffer_data_synced` for data updated every frame to avoid that wait
     #[inline]
     pub fn set_buffer_data<T: BufferDataType>(&mut self, buffer: &Buffer, data: &[T]) {
+        self.update_index_format::<T>(buffer);
         self.backend
             .set_buffer_data(buffer.id(), bytemuck::cast_slice(data));
     }
+
+    /// Index buffers take the format of the last `u8`, `u16` or `u32` data uploaded to them
+    #[inline]
+    fn update_index_format<T: BufferDataType>(&mut self, buffer: &Buffer) {
+        if let (BufferUsage::Index, Some(format)) = (buffer.usage, T::INDEX_FORMAT) {
+            self.backend.set_index_format(buffer.id(), format);
+        }
+    }
+
+    /// Upload the matrices as column-major f32 data, like the `mat4` arrays of a uniform block
+    pub fn set_buffer_matrices(&mut self, buffer: &Buffer, matrices: &[glam::Mat4]) {
+        let data = matrices
+            .iter()
+            .flat_map(|m| m.to_cols_array())
+            .collect::<Vec<f32>>();
+        self.set_buffer_data(buffer, &data);
+    }
+
+    /// Upload the data into a new storage of the buffer (orphaning the old one),
+    /// so the draws still reading the old data don't block or see a partial update
+    #[inline]
+    pub fn set_buffer_data_synced<T: BufferDataType>(&mut self, buffer: &Buffer, data: &[T]) {
+        self.update_index_format::<T>(buffer);
+        self.backend
+            .set_buffer_data_orphaned(buffer.id(), bytemuck::cast_slice(data));
+    }
+
+    /// Upload bytes as they are, for data with a layout built by hand
+    #[inline]
+    pub fn set_buffer_data_raw(&mut self, buffer: &Buffer, data: &[u8]) {
+        self.backend.set_buffer_data(buffer.id(), data);
+    }
+
+    /// Upload only the dirty ranges of a buffer already allocated with `set_buffer_data`.
+    /// Offsets are counted in elements of `T`, contiguous ranges are merged in one upload.
+    /// Fails on the first range out of the allocated size, the ones before it are uploaded
+    pub fn set_buffer_data_ranges<T: BufferDataType>(
+        &mut self,
+        buffer: &Buffer,
+        ranges: &[(usize, &[T])],
+    ) -> Result<(), String> {
+        self.update_index_format::<T>(buffer);
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by_key(|(offset, _)| *offset);
+
+        let mut runs: Vec<(usize, usize, Vec<&[u8]>)> = vec![];
+        for (offset, data) in ranges {
+            let bytes: &[u8] = bytemuck::cast_slice(data);
+            let start = offset * std::mem::size_of::<T>();
+
+            match runs.last_mut() {
+                Some((run_start, run_len, slices)) if *run_start + *run_len == start => {
+                    *run_len += bytes.len();
+                    slices.push(bytes);
+                }
+                _ => runs.push((start, bytes.len(), vec![bytes])),
+            }
+        }
+
+        runs.iter().try_for_each(|(start, _, slices)| {
+            if slices.len() == 1 {
+                self.backend
+                    .set_buffer_sub_data(buffer.id(), *start, slices[0])
+            } else {
+                self.backend
+                    .set_buffer_sub_data(buffer.id(), *start, &slices.concat())
+            }
+        })
+    }
 }
 
-pub trait BufferDataType: bytemuck::Pod {}
-impl BufferDataType for u32 {}
+pub trait BufferDataType: bytemuck::Pod {
+    /// Format of the indices when the data is uploaded to an index buffer
+    const INDEX_FORMAT: Option<IndexFormat> = None;
+}
+impl BufferDataType for u8 {
+    const INDEX_FORMAT: Option<IndexFormat> = Some(IndexFormat::UInt8);
+}
+impl BufferDataType for u16 {
+    const INDEX_FORMAT: Option<IndexFormat> = Some(IndexFormat::UInt16);
+}
+impl BufferDataType for u32 {
+    const INDEX_FORMAT: Option<IndexFormat> = Some(IndexFormat::UInt32);
+}
+impl BufferDataType for i32 {}
 impl BufferDataType for f32 {}
+
+/// Catch a missing `begin` or `end` in debug builds
+#[inline(always)]
+fn debug_validate_commands(commands: &[Commands]) {
+    if cfg!(debug_assertions) {
+        if let Err(err) = validate_commands(commands) {
+            panic!("Invalid commands: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::buffer::{VertexFormat, VertexInfo};
+
+    /// Backend recording the buffer uploads, the rest of the api isn't used by the tests
+    #[derive(Default)]
+    struct RecordingBackend {
+        buffer_count: u64,
+        uploads: Vec<(u64, Vec<u8>)>,
+        index_formats: Vec<(u64, IndexFormat)>,
+    }
+
+    impl DeviceBackend for RecordingBackend {
+        fn create_pipeline(
+            &mut self,
+            _vertex_source: &[u8],
+            _fragment_source: &[u8],
+            _vertex_attrs: &[VertexAttr],
+            _feedback_varyings: &[String],
+            _options: PipelineOptions,
+        ) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn create_vertex_buffer(
+            &mut self,
+            _attrs: &[VertexAttr],
+            _step_mode: VertexStepMode,
+            _stride: u32,
+        ) -> Result<u64, String> {
+            self.create_index_buffer()
+        }
+
+        fn create_index_buffer(&mut self) -> Result<u64, String> {
+            self.buffer_count += 1;
+            Ok(self.buffer_count)
+        }
+
+        fn create_uniform_buffer(&mut self, _slot: u32, _name: &str) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn create_storage_buffer(&mut self, _slot: u32, _name: &str) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn create_sampler(&mut self, _info: &SamplerInfo) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn create_compute_pipeline(&mut self, _source: &[u8]) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn set_buffer_data(&mut self, buffer: u64, data: &[u8]) {
+            self.uploads.push((buffer, data.to_vec()));
+        }
+
+        fn set_index_format(&mut self, buffer: u64, format: IndexFormat) {
+            self.index_formats.push((buffer, format));
+        }
+
+        fn set_buffer_data_orphaned(&mut self, _buffer: u64, _data: &[u8]) {
+            unimplemented!()
+        }
+
+        fn set_buffer_sub_data(
+            &mut self,
+            _buffer: u64,
+            _offset: usize,
+            _data: &[u8],
+        ) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn render(&mut self, _commands: &[Commands], _target: Option<u64>) {
+            unimplemented!()
+        }
+
+        fn texture_ids(&self) -> Vec<u64> {
+            unimplemented!()
+        }
+
+        fn pipeline_ids(&self) -> Vec<u64> {
+            unimplemented!()
+        }
+
+        fn clean(&mut self, _to_clean: &[ResourceId]) {
+            unimplemented!()
+        }
+
+        fn set_size(&mut self, _width: i32, _height: i32) {
+            unimplemented!()
+        }
+
+        fn set_dpi(&mut self, _scale_factor: f64) {
+            unimplemented!()
+        }
+
+        fn set_mipmap_hint(&mut self, _hint: MipmapHint) {
+            unimplemented!()
+        }
+
+        fn set_letterbox(&mut self, _rect: Option<Rect>) {
+            unimplemented!()
+        }
+
+        fn create_texture(&mut self, _info: &TextureInfo) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn create_render_texture(
+            &mut self,
+            _texture_id: u64,
+            _depth_texture_id: Option<u64>,
+            _info: &TextureInfo,
+        ) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn resize_render_texture(
+            &mut self,
+            _render_texture: u64,
+            _texture_id: u64,
+            _depth_texture_id: Option<u64>,
+            _info: &TextureInfo,
+        ) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn pipeline_attributes(&self, _pipeline: u64) -> Vec<AttributeInfo> {
+            unimplemented!()
+        }
+
+        fn is_format_renderable(&mut self, _format: TextureFormat) -> bool {
+            unimplemented!()
+        }
+
+        fn copy_render_texture_to_texture(&mut self, _src: u64, _dst: u64) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn set_render_texture_face(&mut self, _id: u64, _face: CubeFace) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn blit(
+            &mut self,
+            _src: u64,
+            _dst: u64,
+            _src_rect: &Rect,
+            _dst_rect: &Rect,
+            _filter: &TextureFilter,
+        ) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn update_texture(&mut self, _texture: u64, _opts: &TextureUpdate) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn read_pixels(
+            &mut self,
+            _texture: u64,
+            _bytes: &mut [u8],
+            _opts: &TextureRead,
+        ) -> Result<(), String> {
+            unimplemented!()
+        }
+
+        fn read_screen(&mut self, _x: i32, _y: i32, _width: i32, _height: i32, _bytes: &mut [u8]) {
+            unimplemented!()
+        }
+
+        fn swap_buffers(&mut self) {
+            unimplemented!()
+        }
+
+        fn barrier(&mut self) {
+            unimplemented!()
+        }
+
+        fn memory_barrier(&mut self, _flags: BarrierFlags) {
+            unimplemented!()
+        }
+
+        fn stats(&self) -> FrameStats {
+            unimplemented!()
+        }
+
+        fn begin_occlusion_query(&mut self) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn end_occlusion_query(&mut self, _query: u64) {
+            unimplemented!()
+        }
+
+        fn occlusion_result(&mut self, _query: u64) -> Option<bool> {
+            unimplemented!()
+        }
+
+        fn delete_occlusion_query(&mut self, _query: u64) {
+            unimplemented!()
+        }
+
+        fn insert_fence(&mut self) -> Result<u64, String> {
+            unimplemented!()
+        }
+
+        fn wait_fence(&mut self, _fence: u64, _timeout: Duration) -> bool {
+            unimplemented!()
+        }
+
+        fn is_fence_signaled(&mut self, _fence: u64) -> bool {
+            unimplemented!()
+        }
+
+        fn delete_fence(&mut self, _fence: u64) {
+            unimplemented!()
+        }
+
+        fn reset_gl_state(&mut self) {
+            unimplemented!()
+        }
+
+        fn set_reset_gl_state_on_end(&mut self, _enable: bool) {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn buffer_data_uploads_the_bytes_of_each_type() {
+        let mut device = Device::new(RecordingBackend::default());
+        let indices = device.create_index_buffer().build().unwrap();
+        let vertices = device
+            .create_vertex_buffer()
+            .with_info(&VertexInfo::new().attr(0, VertexFormat::Float32x2))
+            .build()
+            .unwrap();
+
+        device.set_buffer_data(&indices, &[1u8, 2, 3]);
+        device.set_buffer_data(&indices, &[1u16, 0x0302]);
+        device.set_buffer_data(&indices, &[0x04030201u32]);
+        device.set_buffer_data(&vertices, &[-1i32]);
+        device.set_buffer_data(&vertices, &[1u16]);
+
+        let backend = &device.backend;
+        assert_eq!(
+            backend.uploads,
+            vec![
+                (indices.id(), vec![1, 2, 3]),
+                (
+                    indices.id(),
+                    1u16.to_ne_bytes()
+                        .into_iter()
+                        .chain(0x0302u16.to_ne_bytes())
+                        .collect()
+                ),
+                (indices.id(), 0x04030201u32.to_ne_bytes().to_vec()),
+                (vertices.id(), vec![0xff; 4]),
+                (vertices.id(), 1u16.to_ne_bytes().to_vec()),
+            ]
+        );
+
+        // only the index buffer takes the format of the data
+        assert_eq!(
+            backend.index_formats,
+            vec![
+                (indices.id(), IndexFormat::UInt8),
+                (indices.id(), IndexFormat::UInt16),
+                (indices.id(), IndexFormat::UInt32),
+            ]
+        );
+    }
+
+    #[test]
+    fn index_format_matches_data_type() {
+        fn check<T: BufferDataType>(format: IndexFormat) {
+            assert_eq!(T::INDEX_FORMAT, Some(format));
+            assert_eq!(format.size(), std::mem::size_of::<T>());
+        }
+
+        check::<u8>(IndexFormat::UInt8);
+        check::<u16>(IndexFormat::UInt16);
+        check::<u32>(IndexFormat::UInt32);
+        assert_eq!(<i32 as BufferDataType>::INDEX_FORMAT, None);
+        assert_eq!(<f32 as BufferDataType>::INDEX_FORMAT, None);
+    }
+}
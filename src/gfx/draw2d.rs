@@ -0,0 +1,216 @@
+use super::{
+    buffer::{Buffer, VertexFormat, VertexInfo},
+    color::Color,
+    device::{Device, DeviceBackend},
+    encoder::CommandEncoder,
+    pipeline::{BlendMode, DrawPrimitive, Pipeline},
+    rect::Rect,
+};
+
+const VERT: &str = r#"
+    #version 310 es
+    layout(location = 0) in vec2 a_pos;
+    layout(location = 1) in vec4 a_color;
+
+    layout(location = 0) out vec4 v_color;
+
+    void main() {
+        v_color = a_color;
+        gl_Position = vec4(a_pos, 0.0, 1.0);
+    }
+"#;
+
+const FRAG: &str = r#"
+    #version 310 es
+    precision mediump float;
+
+    layout(location = 0) in vec4 v_color;
+    layout(location = 0) out vec4 color;
+
+    void main() {
+        color = v_color;
+    }
+"#;
+
+/// Floats per vertex: position xy + color rgba
+const VERTEX_SIZE: usize = 6;
+
+/// Accumulates 2D shapes with per vertex colors and draws all of them with one draw call.
+///
+/// Positions are in pixels with the origin at the top-left of the target.
+/// The vertex data is uploaded on `flush`, so it should be called once per encoder
+/// rendered, a second flush before rendering would overwrite the data of the first one.
+pub struct Batch {
+    pipeline: Pipeline,
+    vbo: Buffer,
+    vertices: Vec<f32>,
+    upload: Vec<f32>,
+    line_width: f32,
+}
+
+impl Batch {
+    pub fn new<B: DeviceBackend>(device: &mut Device<B>) -> Result<Self, String> {
+        let vertex_info = VertexInfo::new()
+            .attr(0, VertexFormat::Float32x2)
+            .attr(1, VertexFormat::Float32x4);
+
+        let pipeline = device
+            .create_pipeline()
+            .from(VERT, FRAG)
+            .with_vertex_info(&vertex_info)
+            .with_color_blend(BlendMode::NORMAL)
+            .build()?;
+
+        let vbo = device
+            .create_vertex_buffer()
+            .with_info(&vertex_info)
+            .build()?;
+
+        Ok(Self {
+            pipeline,
+            vbo,
+            vertices: vec![],
+            upload: vec![],
+            line_width: 1.0,
+        })
+    }
+
+    /// Width in pixels used to draw the lines
+    pub fn set_line_width(&mut self, width: f32) {
+        self.line_width = width;
+    }
+
+    pub fn line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    /// Number of vertices waiting to be flushed
+    pub fn len(&self) -> usize {
+        self.vertices.len() / VERTEX_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn triangle(&mut self, a: (f32, f32), b: (f32, f32), c: (f32, f32), color: Color) {
+        self.triangle_colors([a, b, c], [color; 3]);
+    }
+
+    /// Triangle with a color per vertex
+    pub fn triangle_colors(&mut self, points: [(f32, f32); 3], colors: [Color; 3]) {
+        points
+            .iter()
+            .zip(colors.iter())
+            .for_each(|(p, c)| self.vertex(*p, *c));
+    }
+
+    pub fn rect(&mut self, rect: Rect, color: Color) {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = rect;
+
+        self.quad(
+            [
+                (x, y),
+                (x + width, y),
+                (x + width, y + height),
+                (x, y + height),
+            ],
+            color,
+        );
+    }
+
+    pub fn line(&mut self, a: (f32, f32), b: (f32, f32), color: Color) {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            return;
+        }
+
+        let half = self.line_width * 0.5;
+        let (nx, ny) = (-dy / len * half, dx / len * half);
+
+        self.quad(
+            [
+                (a.0 + nx, a.1 + ny),
+                (b.0 + nx, b.1 + ny),
+                (b.0 - nx, b.1 - ny),
+                (a.0 - nx, a.1 - ny),
+            ],
+            color,
+        );
+    }
+
+    /// Upload the shapes and record the draw on the encoder, the batch is empty after it
+    pub fn flush<B: DeviceBackend>(
+        &mut self,
+        device: &mut Device<B>,
+        encoder: &mut CommandEncoder,
+    ) {
+        flush_pixel_triangles(
+            device,
+            encoder,
+            &self.vertices,
+            VERTEX_SIZE,
+            &mut self.upload,
+            &self.vbo,
+            |encoder| {
+                encoder.set_pipeline(&self.pipeline);
+                encoder.bind_buffer(&self.vbo);
+            },
+        );
+
+        self.vertices.clear();
+    }
+
+    fn quad(&mut self, points: [(f32, f32); 4], color: Color) {
+        let [a, b, c, d] = points;
+        self.triangle(a, b, c, color);
+        self.triangle(a, c, d, color);
+    }
+
+    #[inline]
+    fn vertex(&mut self, pos: (f32, f32), color: Color) {
+        self.vertices
+            .extend_from_slice(&[pos.0, pos.1, color.r, color.g, color.b, color.a]);
+    }
+}
+
+/// Upload vertices with the pixel position in their first two floats as NDC into `vbo`
+/// and record a triangle list draw of them, restoring the primitive of the encoder.
+/// `bind` sets the pipeline and binds the buffer and textures used by the draw
+pub(crate) fn flush_pixel_triangles<B: DeviceBackend>(
+    device: &mut Device<B>,
+    encoder: &mut CommandEncoder,
+    vertices: &[f32],
+    vertex_size: usize,
+    upload: &mut Vec<f32>,
+    vbo: &Buffer,
+    bind: impl FnOnce(&mut CommandEncoder),
+) {
+    if vertices.is_empty() {
+        return;
+    }
+
+    let (width, height) = encoder.size();
+    let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+
+    upload.clear();
+    vertices.chunks(vertex_size).for_each(|v| {
+        upload.push(v[0] / width * 2.0 - 1.0);
+        upload.push(1.0 - v[1] / height * 2.0);
+        upload.extend_from_slice(&v[2..]);
+    });
+
+    device.set_buffer_data(vbo, upload);
+
+    let primitive = encoder.primitive();
+    encoder.set_primitive(DrawPrimitive::Triangles);
+    bind(encoder);
+    encoder.draw(0, (vertices.len() / vertex_size) as _);
+    encoder.set_primitive(primitive);
+}
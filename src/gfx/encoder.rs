@@ -1,15 +1,35 @@
 use super::{
     buffer::Buffer,
-    commands::Commands,
-    pipeline::{ClearOptions, DrawPrimitive, Pipeline},
-    texture::Texture,
+    commands::{validate_commands, Commands, UniformValue},
+    material::Material,
+    pipeline::{ClearOptions, CompareMode, ComputePipeline, DrawPrimitive, Pipeline},
+    rect::Rect,
+    sampler::Sampler,
+    texture::{ImageAccess, Texture, TextureFormat, MAX_TEXTURE_SLOTS},
 };
+use std::time::{Duration, Instant};
+
+/// Number of commands of each kind recorded by an encoder
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CommandsSummary {
+    pub passes: usize,
+    pub draws: usize,
+    pub instanced_draws: usize,
+    pub pipeline_binds: usize,
+    pub buffer_binds: usize,
+    pub texture_binds: usize,
+}
 
 #[derive(Default, Clone)]
 pub struct CommandEncoder {
     commands: Vec<Commands>,
     size: (i32, i32),
     primitive: DrawPrimitive,
+    viewport: Option<Rect>,
+    viewport_stack: Vec<Option<Rect>>,
+    /// Start of the pass being recorded
+    pass_start: Option<Instant>,
+    encode_duration: Duration,
 }
 
 impl CommandEncoder {
@@ -18,17 +38,29 @@ impl CommandEncoder {
             size: (width, height),
             commands: vec![Commands::Size { width, height }],
             primitive: DrawPrimitive::Triangles,
+            viewport: None,
+            viewport_stack: vec![],
+            pass_start: None,
+            encode_duration: Duration::ZERO,
         }
     }
 
     pub fn begin(&mut self, options: Option<&ClearOptions>) {
-        let (color, stencil, depth) = match options {
-            Some(opts) => (opts.color, opts.stencil, opts.depth),
-            _ => (None, None, None),
+        self.pass_start = Some(Instant::now());
+
+        let (color, colors, stencil, depth) = match options {
+            Some(opts) => (
+                opts.clear_color(),
+                opts.clear_colors(),
+                opts.stencil,
+                opts.depth,
+            ),
+            _ => (None, vec![], None, None),
         };
 
         self.commands.push(Commands::Begin {
             color,
+            colors,
             stencil,
             depth,
         });
@@ -38,8 +70,32 @@ impl CommandEncoder {
         self.primitive = primitive;
     }
 
+    pub fn primitive(&self) -> DrawPrimitive {
+        self.primitive
+    }
+
     pub fn end(&mut self) {
         self.commands.push(Commands::End);
+
+        if let Some(start) = self.pass_start.take() {
+            self.encode_duration += start.elapsed();
+        }
+    }
+
+    /// CPU time spent recording the passes, from each `begin` to its `end`
+    #[inline]
+    pub fn encode_duration(&self) -> Duration {
+        self.encode_duration
+    }
+
+    /// Record a render pass, `begin` and `end` are added around the commands of the closure
+    pub fn pass<F>(&mut self, options: Option<&ClearOptions>, f: F)
+    where
+        F: FnOnce(&mut RenderPass),
+    {
+        self.begin(options);
+        f(&mut RenderPass { encoder: self });
+        self.end();
     }
 
     pub fn set_size(&mut self, width: i32, height: i32) {
@@ -60,6 +116,12 @@ impl CommandEncoder {
     }
 
     pub fn set_viewport(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.viewport = Some(Rect {
+            x,
+            y,
+            width,
+            height,
+        });
         self.commands.push(Commands::Viewport {
             x,
             y,
@@ -68,6 +130,40 @@ impl CommandEncoder {
         });
     }
 
+    /// Set the viewport remembering the current one so it can be restored with `pop_viewport`
+    pub fn push_viewport(&mut self, rect: Rect) {
+        self.viewport_stack.push(self.viewport);
+        self.set_viewport(rect.x, rect.y, rect.width, rect.height);
+    }
+
+    /// Restore the viewport set before the last `push_viewport`
+    pub fn pop_viewport(&mut self) {
+        if let Some(previous) = self.viewport_stack.pop() {
+            let rect = previous.unwrap_or(Rect {
+                x: 0.0,
+                y: 0.0,
+                width: self.size.0 as _,
+                height: self.size.1 as _,
+            });
+
+            self.set_viewport(rect.x, rect.y, rect.width, rect.height);
+            self.viewport = previous;
+        }
+    }
+
+    /// Record the commands of the closure once per viewport, useful for split screen
+    /// or picture in picture. The closure receives the index of the viewport
+    pub fn for_each_viewport<F>(&mut self, viewports: &[Rect], mut f: F)
+    where
+        F: FnMut(&mut Self, usize),
+    {
+        viewports.iter().enumerate().for_each(|(i, rect)| {
+            self.push_viewport(*rect);
+            f(self, i);
+            self.pop_viewport();
+        });
+    }
+
     pub fn set_scissors(&mut self, x: f32, y: f32, width: f32, height: f32) {
         self.commands.push(Commands::Scissors {
             x,
@@ -84,6 +180,42 @@ impl CommandEncoder {
         });
     }
 
+    /// Set the compute pipeline used by the next `dispatch` calls
+    pub fn set_compute_pipeline(&mut self, pipeline: &ComputePipeline) {
+        self.commands
+            .push(Commands::ComputePipeline { id: pipeline.id() });
+    }
+
+    /// Change the depth test of the current pipeline without binding it again,
+    /// the pipeline's value is restored the next time it's set
+    pub fn set_depth_compare(&mut self, compare: CompareMode) {
+        self.commands.push(Commands::DepthCompare { compare });
+    }
+
+    /// Enable or disable the depth writes of the current pipeline without binding it again,
+    /// the pipeline's value is restored the next time it's set
+    pub fn set_depth_write(&mut self, enabled: bool) {
+        self.commands.push(Commands::DepthWrite { enabled });
+    }
+
+    /// Select the color attachments written by the next draws, `None` disables the writes
+    /// to the attachment at that position (e.g. for a depth only prepass).
+    /// The entry `i` can only be `Some(i)` or `None`, the state is kept by the
+    /// framebuffer until it's set again
+    pub fn set_draw_buffers(&mut self, buffers: &[Option<u32>]) {
+        self.commands.push(Commands::DrawBuffers {
+            buffers: buffers.to_vec(),
+        });
+    }
+
+    /// Change the stencil reference value of the current pipeline without binding it again,
+    /// the pipeline's value is restored the next time it's set
+    pub fn set_stencil_ref(&mut self, reference: u32) {
+        self.commands.push(Commands::StencilReference { reference });
+    }
+
+    /// Uniform and storage buffers can be bound before the pipeline,
+    /// their blocks are resolved once a pipeline is set
     pub fn bind_buffer(&mut self, buffer: &Buffer) {
         self.commands.push(Commands::BindBuffer { id: buffer.id() });
     }
@@ -93,6 +225,17 @@ impl CommandEncoder {
             .extend(buffers.iter().map(|b| Commands::BindBuffer { id: b.id() }));
     }
 
+    /// Bind a part of a uniform buffer to `slot`, to keep the blocks of many draws in one buffer.
+    /// `offset` must be a multiple of `Limits::uniform_buffer_offset_alignment`, checked when rendering
+    pub fn bind_uniform_range(&mut self, buffer: &Buffer, slot: u32, offset: u32, size: u32) {
+        self.commands.push(Commands::BindUniformRange {
+            id: buffer.id(),
+            slot,
+            offset,
+            size,
+        });
+    }
+
     pub fn draw(&mut self, offset: i32, count: i32) {
         self.commands.push(Commands::Draw {
             primitive: self.primitive,
@@ -101,6 +244,36 @@ impl CommandEncoder {
         })
     }
 
+    /// Indexed draw where all the indices read are between `start` and `end` (inclusive),
+    /// the range is a hint that lets the driver skip the vertices not used by a sub-mesh
+    pub fn draw_range_elements(&mut self, start: u32, end: u32, offset: i32, count: i32) {
+        self.commands.push(Commands::DrawRangeElements {
+            primitive: self.primitive,
+            start,
+            end,
+            offset,
+            count,
+        })
+    }
+
+    /// Indexed draw of a mesh packed with others in shared buffers, `base_vertex` is added
+    /// to the indices so they can be relative to the first vertex of the mesh.
+    /// Needs `Limits::base_vertex`, GLES 3.2 or `OES_draw_elements_base_vertex`
+    pub fn draw_indexed_base_vertex(&mut self, index_offset: i32, count: i32, base_vertex: i32) {
+        self.commands.push(Commands::DrawBaseVertex {
+            primitive: self.primitive,
+            offset: index_offset,
+            count,
+            base_vertex,
+        })
+    }
+
+    /// Run the current compute pipeline with the given number of work groups,
+    /// the writes are visible to the commands after it
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) {
+        self.commands.push(Commands::Dispatch { x, y, z });
+    }
+
     pub fn draw_instanced(&mut self, offset: i32, count: i32, length: i32) {
         self.commands.push(Commands::DrawInstanced {
             primitive: self.primitive,
@@ -110,6 +283,33 @@ impl CommandEncoder {
         })
     }
 
+    /// Capture the vertex shader outputs of the next draws into the buffer,
+    /// the pipeline must be created with `Device::create_transform_feedback`.
+    /// Only `Points`, `Lines` and `Triangles` can be captured, the draws must use the same
+    /// primitive and can't use an index buffer
+    pub fn begin_transform_feedback(&mut self, primitive: DrawPrimitive, buffer: &Buffer) {
+        self.commands.push(Commands::BeginTransformFeedback {
+            primitive,
+            buffer: buffer.id(),
+        });
+    }
+
+    pub fn end_transform_feedback(&mut self) {
+        self.commands.push(Commands::EndTransformFeedback);
+    }
+
+    /// Indexed draws will start a new primitive when `PRIMITIVE_RESTART_INDEX` is found,
+    /// allowing to draw many triangle strips with one draw call. Disabled again on `end`
+    pub fn enable_primitive_restart(&mut self) {
+        self.commands
+            .push(Commands::PrimitiveRestart { enabled: true });
+    }
+
+    pub fn disable_primitive_restart(&mut self) {
+        self.commands
+            .push(Commands::PrimitiveRestart { enabled: false });
+    }
+
     pub fn bind_texture(&mut self, location: u32, texture: &Texture) {
         self.bind_texture_slot(0, location, texture);
     }
@@ -119,14 +319,298 @@ impl CommandEncoder {
             slot,
             location,
             id: texture.id(),
+            sampler: None,
+        })
+    }
+
+    /// Set a uniform of the current pipeline, `location` is the index of the uniform
+    /// like the one of `bind_texture`. For small values that don't need a uniform buffer
+    pub fn set_uniform_f32(&mut self, location: u32, value: f32) {
+        self.commands.push(Commands::Uniform {
+            location,
+            value: UniformValue::F32(value),
+        });
+    }
+
+    pub fn set_uniform_vec4(&mut self, location: u32, value: [f32; 4]) {
+        self.commands.push(Commands::Uniform {
+            location,
+            value: UniformValue::Vec4(value),
+        });
+    }
+
+    /// Set a column-major matrix uniform of the current pipeline
+    pub fn set_uniform_mat4(&mut self, location: u32, value: [f32; 16]) {
+        self.commands.push(Commands::Uniform {
+            location,
+            value: UniformValue::Mat4(value),
+        });
+    }
+
+    /// Bind the texture sampled with the filtering of `sampler` instead of its own
+    pub fn bind_texture_sampler(
+        &mut self,
+        slot: u32,
+        location: u32,
+        texture: &Texture,
+        sampler: &Sampler,
+    ) {
+        self.commands.push(Commands::BindTexture {
+            slot,
+            location,
+            id: texture.id(),
+            sampler: Some(sampler.id()),
         })
     }
 
+    /// Bind the level 0 of a texture to an image unit, for `imageLoad` and `imageStore` in
+    /// compute shaders. The texture needs `with_image_storage` and `Limits::image_load_store`,
+    /// `format` is the one of the image declared in the shader and must match the texture one
+    pub fn bind_image_texture(
+        &mut self,
+        texture: &Texture,
+        unit: u32,
+        access: ImageAccess,
+        format: TextureFormat,
+    ) -> Result<(), String> {
+        if !format.is_image_storable() {
+            return Err(format!("{:?} can't be used as an image format", format));
+        }
+
+        if texture.format() != &format {
+            return Err(format!(
+                "Image format {:?} doesn't match the {:?} format of the texture",
+                format,
+                texture.format()
+            ));
+        }
+
+        self.commands.push(Commands::BindImageTexture {
+            id: texture.id(),
+            unit,
+            access,
+            format,
+        });
+
+        Ok(())
+    }
+
+    /// Bind a group of textures with one command, each one as (texture, slot, location).
+    /// The slots are checked against `Limits::max_texture_image_units` when rendering
+    pub fn bind_textures(&mut self, textures: &[(&Texture, u32, u32)]) -> Result<(), String> {
+        if let Some((_, slot, _)) = textures
+            .iter()
+            .find(|(_, slot, _)| *slot >= MAX_TEXTURE_SLOTS)
+        {
+            return Err(format!(
+                "Unsupported texture slot '{}', the max slot is {}",
+                slot,
+                MAX_TEXTURE_SLOTS - 1
+            ));
+        }
+
+        self.commands.push(Commands::BindTextures {
+            textures: textures
+                .iter()
+                .map(|(texture, slot, location)| (texture.id(), *slot, *location))
+                .collect(),
+        });
+
+        Ok(())
+    }
+
+    /// Last viewport set on the encoder, `None` means the whole target
+    pub fn current_viewport(&self) -> Option<Rect> {
+        self.viewport
+    }
+
+    /// Count the recorded draws and binds, useful to verify what a pass does
+    pub fn commands_summary(&self) -> CommandsSummary {
+        self.commands
+            .iter()
+            .fold(CommandsSummary::default(), |mut summary, cmd| {
+                match cmd {
+                    Commands::Begin { .. } => summary.passes += 1,
+                    Commands::Draw { .. }
+                    | Commands::DrawRangeElements { .. }
+                    | Commands::DrawBaseVertex { .. } => summary.draws += 1,
+                    Commands::DrawInstanced { .. } => summary.instanced_draws += 1,
+                    Commands::Pipeline { .. } | Commands::ComputePipeline { .. } => {
+                        summary.pipeline_binds += 1
+                    }
+                    Commands::BindBuffer { .. } | Commands::BindUniformRange { .. } => {
+                        summary.buffer_binds += 1
+                    }
+                    Commands::BindTexture { .. } => summary.texture_binds += 1,
+                    Commands::BindTextures { textures } => summary.texture_binds += textures.len(),
+                    _ => {}
+                }
+                summary
+            })
+    }
+
+    /// Check that every draw is between `begin` and `end`
+    pub fn validate(&self) -> Result<(), String> {
+        validate_commands(&self.commands)
+    }
+
     pub fn clear(&mut self) {
         self.commands.clear();
+        self.viewport = None;
+        self.viewport_stack.clear();
+        self.pass_start = None;
+        self.encode_duration = Duration::ZERO;
+    }
+
+    /// Clear the encoder keeping the allocated memory, to reuse it on the next frame
+    pub fn reset(&mut self, width: i32, height: i32) {
+        self.clear();
+        self.primitive = DrawPrimitive::Triangles;
+        self.set_size(width, height);
     }
 
     pub fn commands(&self) -> &[Commands] {
         &self.commands
     }
 }
+
+/// Forward methods of the pass to the same ones of its encoder, the list is the only place
+/// to add them so the pass keeps up with the encoder
+macro_rules! forward_to_encoder {
+    ($(fn $name:ident(&mut self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)*) => {
+        $(
+            #[inline]
+            pub fn $name(&mut self $(, $arg: $ty)*) $(-> $ret)? {
+                self.encoder().$name($($arg),*)
+            }
+        )*
+    };
+}
+
+/// Commands that can be recorded inside a pass created with `CommandEncoder::pass`
+pub struct RenderPass<'a> {
+    encoder: &'a mut CommandEncoder,
+}
+
+impl<'a> RenderPass<'a> {
+    #[inline]
+    fn encoder(&mut self) -> &mut CommandEncoder {
+        self.encoder
+    }
+
+    #[inline]
+    pub fn size(&self) -> (i32, i32) {
+        self.encoder.size()
+    }
+
+    #[inline]
+    pub fn primitive(&self) -> DrawPrimitive {
+        self.encoder.primitive()
+    }
+
+    #[inline]
+    pub fn current_viewport(&self) -> Option<Rect> {
+        self.encoder.current_viewport()
+    }
+
+    /// Set the pipeline of the material and bind its buffers and textures
+    #[inline]
+    pub fn set_material(&mut self, material: &Material) {
+        material.bind(self.encoder);
+    }
+
+    forward_to_encoder! {
+        fn set_primitive(&mut self, primitive: DrawPrimitive);
+        fn set_viewport(&mut self, x: f32, y: f32, width: f32, height: f32);
+        fn push_viewport(&mut self, rect: Rect);
+        fn pop_viewport(&mut self);
+        fn set_scissors(&mut self, x: f32, y: f32, width: f32, height: f32);
+        fn set_pipeline(&mut self, pipeline: &Pipeline);
+        fn set_compute_pipeline(&mut self, pipeline: &ComputePipeline);
+        fn set_depth_compare(&mut self, compare: CompareMode);
+        fn set_depth_write(&mut self, enabled: bool);
+        fn set_draw_buffers(&mut self, buffers: &[Option<u32>]);
+        fn set_stencil_ref(&mut self, reference: u32);
+        fn bind_buffer(&mut self, buffer: &Buffer);
+        fn bind_buffers(&mut self, buffers: &[&Buffer]);
+        fn bind_uniform_range(&mut self, buffer: &Buffer, slot: u32, offset: u32, size: u32);
+        fn bind_texture(&mut self, location: u32, texture: &Texture);
+        fn bind_texture_slot(&mut self, slot: u32, location: u32, texture: &Texture);
+        fn bind_texture_sampler(&mut self, slot: u32, location: u32, texture: &Texture, sampler: &Sampler);
+        fn bind_textures(&mut self, textures: &[(&Texture, u32, u32)]) -> Result<(), String>;
+        fn bind_image_texture(&mut self, texture: &Texture, unit: u32, access: ImageAccess, format: TextureFormat) -> Result<(), String>;
+        fn set_uniform_f32(&mut self, location: u32, value: f32);
+        fn set_uniform_vec4(&mut self, location: u32, value: [f32; 4]);
+        fn set_uniform_mat4(&mut self, location: u32, value: [f32; 16]);
+        fn enable_primitive_restart(&mut self);
+        fn disable_primitive_restart(&mut self);
+        fn draw(&mut self, offset: i32, count: i32);
+        fn draw_range_elements(&mut self, start: u32, end: u32, offset: i32, count: i32);
+        fn draw_indexed_base_vertex(&mut self, index_offset: i32, count: i32, base_vertex: i32);
+        fn draw_instanced(&mut self, offset: i32, count: i32, length: i32);
+        fn dispatch(&mut self, x: u32, y: u32, z: u32);
+        fn begin_transform_feedback(&mut self, primitive: DrawPrimitive, buffer: &Buffer);
+        fn end_transform_feedback(&mut self);
+    }
+}
+
+/// Recycled encoders, to avoid allocating the commands each frame
+#[derive(Default)]
+pub struct CommandEncoderPool {
+    free: Vec<CommandEncoder>,
+}
+
+impl CommandEncoderPool {
+    /// Returns an empty encoder, reusing a released one if there is any
+    pub fn acquire(&mut self, width: i32, height: i32) -> CommandEncoder {
+        match self.free.pop() {
+            Some(mut encoder) => {
+                encoder.reset(width, height);
+                encoder
+            }
+            None => CommandEncoder::new(width, height),
+        }
+    }
+
+    /// Give back an encoder once its commands are rendered
+    pub fn release(&mut self, encoder: CommandEncoder) {
+        self.free.push(encoder);
+    }
+
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass_records_the_same_commands() {
+        let mut manual = CommandEncoder::new(64, 64);
+        manual.begin(None);
+        manual.set_viewport(0.0, 0.0, 32.0, 32.0);
+        manual.enable_primitive_restart();
+        manual.draw(0, 3);
+        manual.dispatch(1, 2, 3);
+        manual.end();
+
+        let mut closure = CommandEncoder::new(64, 64);
+        closure.pass(None, |pass| {
+            pass.set_viewport(0.0, 0.0, 32.0, 32.0);
+            pass.enable_primitive_restart();
+            pass.draw(0, 3);
+            pass.dispatch(1, 2, 3);
+        });
+
+        assert_eq!(
+            format!("{:?}", manual.commands()),
+            format!("{:?}", closure.commands())
+        );
+    }
+}
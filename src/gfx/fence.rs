@@ -0,0 +1,10 @@
+/// Id of a gpu fence inserted by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FenceId(pub(crate) u64);
+
+impl FenceId {
+    #[inline(always)]
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,55 @@
+use super::{render_texture::RenderTexture, texture::TextureFormat};
+
+#[derive(Debug)]
+struct TransientTarget {
+    render_texture: RenderTexture,
+    /// Acquired during the current frame
+    used: bool,
+}
+
+/// Render textures reused between the passes of a frame. A target is handed out again
+/// once all the handles returned for it are dropped, so passes that don't overlap in time
+/// share the same texture storage
+#[derive(Debug, Default)]
+pub(crate) struct TransientPool {
+    targets: Vec<TransientTarget>,
+}
+
+impl TransientPool {
+    /// Returns a free target of the same size and format, if any
+    pub fn acquire(
+        &mut self,
+        width: i32,
+        height: i32,
+        format: TextureFormat,
+    ) -> Option<RenderTexture> {
+        let target = self.targets.iter_mut().find(|target| {
+            let info = target.render_texture.info();
+            info.width == width
+                && info.height == height
+                && info.format == format
+                && target.render_texture.is_unique()
+        })?;
+
+        target.used = true;
+        Some(target.render_texture.clone())
+    }
+
+    /// Keep a new target in the pool and returns a handle to it
+    pub fn insert(&mut self, render_texture: RenderTexture) -> RenderTexture {
+        self.targets.push(TransientTarget {
+            render_texture: render_texture.clone(),
+            used: true,
+        });
+        render_texture
+    }
+
+    /// Drop the targets that were not used during the frame
+    pub fn end_frame(&mut self) {
+        self.targets
+            .retain(|target| target.used || !target.render_texture.is_unique());
+        self.targets
+            .iter_mut()
+            .for_each(|target| target.used = false);
+    }
+}
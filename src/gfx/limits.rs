@@ -4,14 +4,62 @@
 #[derive(Debug, Clone, Copy)]
 pub struct Limits {
     pub max_texture_size: u32,
+    /// Render textures can be smaller than `max_texture_size` on some drivers
+    pub max_renderbuffer_size: u32,
+    /// Width and height of the largest viewport, it limits the window size too
+    pub max_viewport_dims: (u32, u32),
     pub max_uniform_blocks: u32,
+    pub max_texture_image_units: u32,
+    pub max_array_texture_layers: u32,
+    /// Samples per pixel of the window framebuffer, 0 without MSAA
+    pub msaa_samples: u32,
+    /// Min and max size of `DrawPrimitive::Points`, set with `gl_PointSize`
+    pub point_size_range: (f32, f32),
+    /// Min and max width of the line primitives
+    pub line_width_range: (f32, f32),
+    /// Occlusion queries (`ANY_SAMPLES_PASSED`) are available
+    pub occlusion_query: bool,
+    /// Pipelines can use `min_sample_shading`, from GLES 3.2 or `OES_sample_shading`
+    pub sample_shading: bool,
+    /// Textures can be bound as images for compute shaders
+    pub image_load_store: bool,
+    pub max_image_units: u32,
+    /// `draw_indexed_base_vertex` is available, from GLES 3.2 or `OES_draw_elements_base_vertex`
+    pub base_vertex: bool,
+    /// Offsets of `bind_uniform_range` must be a multiple of it
+    pub uniform_buffer_offset_alignment: u32,
+    /// `KHR_parallel_shader_compile` is available, `precompile_pipelines` compiles on driver threads
+    pub parallel_shader_compile: bool,
 }
 
 impl Default for Limits {
     fn default() -> Self {
         Self {
             max_texture_size: 8192,
+            max_renderbuffer_size: 8192,
+            max_viewport_dims: (8192, 8192),
             max_uniform_blocks: 8,
+            max_texture_image_units: 8,
+            max_array_texture_layers: 256,
+            msaa_samples: 0,
+            point_size_range: (1.0, 1.0),
+            line_width_range: (1.0, 1.0),
+            occlusion_query: false,
+            sample_shading: false,
+            image_load_store: false,
+            max_image_units: 0,
+            base_vertex: false,
+            uniform_buffer_offset_alignment: 256,
+            parallel_shader_compile: false,
         }
     }
 }
+
+/// Version and gpu reported by the graphics implementation
+#[derive(Debug, Clone, Default)]
+pub struct BackendInfo {
+    pub version: String,
+    pub renderer: String,
+    pub vendor: String,
+    pub glsl_version: String,
+}
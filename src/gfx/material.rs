@@ -0,0 +1,58 @@
+use super::{buffer::Buffer, encoder::CommandEncoder, pipeline::Pipeline, texture::Texture};
+
+/// Pipeline with the uniform buffers and textures it uses,
+/// bound together in the same order every time with `bind`
+#[derive(Debug, Clone)]
+pub struct Material {
+    pipeline: Pipeline,
+    buffers: Vec<Buffer>,
+    textures: Vec<(Texture, u32, u32)>,
+}
+
+impl Material {
+    pub fn new(pipeline: Pipeline) -> Self {
+        Self {
+            pipeline,
+            buffers: vec![],
+            textures: vec![],
+        }
+    }
+
+    /// Add a uniform or storage buffer bound after the pipeline
+    pub fn with_buffer(mut self, buffer: Buffer) -> Self {
+        self.buffers.push(buffer);
+        self
+    }
+
+    /// Add a texture bound to the slot and uniform location
+    pub fn with_texture(mut self, texture: Texture, slot: u32, location: u32) -> Self {
+        self.textures.push((texture, slot, location));
+        self
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    #[inline]
+    pub fn buffers(&self) -> &[Buffer] {
+        &self.buffers
+    }
+
+    #[inline]
+    pub fn textures(&self) -> &[(Texture, u32, u32)] {
+        &self.textures
+    }
+
+    /// Set the pipeline and bind all the buffers and textures
+    pub fn bind(&self, encoder: &mut CommandEncoder) {
+        encoder.set_pipeline(&self.pipeline);
+        self.buffers
+            .iter()
+            .for_each(|buffer| encoder.bind_buffer(buffer));
+        self.textures.iter().for_each(|(texture, slot, location)| {
+            encoder.bind_texture_slot(*slot, *location, texture)
+        });
+    }
+}
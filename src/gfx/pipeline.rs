@@ -2,8 +2,9 @@ use super::{
     buffer::{VertexAttr, VertexInfo},
     color::Color,
     device::{Device, DeviceBackend, DropManager, ResourceId},
+    shader::{inject_alpha_test, preprocess_includes, validate_vertex_attrs},
 };
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug)]
 struct PipelineIdRef {
@@ -17,11 +18,23 @@ impl Drop for PipelineIdRef {
     }
 }
 
+/// Vertex input of a linked pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub location: u32,
+    /// Array size, 1 for non array attributes
+    pub size: i32,
+    /// Backend type of the attribute, like `GL_FLOAT_VEC3`
+    pub typ: u32,
+}
+
 #[derive(Debug, Clone)]
 pub struct Pipeline {
     id: u64,
     _id_ref: Arc<PipelineIdRef>,
     stride: usize,
+    attributes: Arc<[AttributeInfo]>,
     pub options: PipelineOptions,
 }
 
@@ -35,6 +48,7 @@ impl Pipeline {
     pub(crate) fn new(
         id: u64,
         stride: usize,
+        attributes: Vec<AttributeInfo>,
         options: PipelineOptions,
         drop_manager: Arc<DropManager>,
     ) -> Self {
@@ -44,6 +58,7 @@ impl Pipeline {
             id,
             _id_ref: id_ref,
             stride,
+            attributes: attributes.into(),
             options,
         }
     }
@@ -62,6 +77,76 @@ impl Pipeline {
     pub fn offset(&self) -> usize {
         self.stride / 4
     }
+
+    /// Vertex inputs used by the shader, to bind the vertex streams by name
+    #[inline]
+    pub fn active_attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+}
+
+/// Program with a single compute shader, executed with `CommandEncoder::dispatch`
+#[derive(Debug, Clone)]
+pub struct ComputePipeline {
+    id: u64,
+    _id_ref: Arc<PipelineIdRef>,
+}
+
+impl std::cmp::PartialEq for ComputePipeline {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl ComputePipeline {
+    pub(crate) fn new(id: u64, drop_manager: Arc<DropManager>) -> Self {
+        let id_ref = Arc::new(PipelineIdRef { id, drop_manager });
+
+        Self {
+            id,
+            _id_ref: id_ref,
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// Shaders, vertex layout and options of a pipeline compiled by `Device::precompile_pipelines`
+#[derive(Debug, Clone)]
+pub struct PipelineDesc<'a> {
+    pub vertex: &'a str,
+    pub fragment: &'a str,
+    pub vertex_info: Option<&'a VertexInfo>,
+    pub options: PipelineOptions,
+}
+
+impl<'a> PipelineDesc<'a> {
+    pub fn new(vertex: &'a str, fragment: &'a str) -> Self {
+        Self {
+            vertex,
+            fragment,
+            vertex_info: None,
+            options: Default::default(),
+        }
+    }
+
+    pub fn with_vertex_info(mut self, info: &'a VertexInfo) -> Self {
+        self.vertex_info = Some(info);
+        self
+    }
+
+    pub fn with_options(mut self, options: PipelineOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Attributes of the vertex info, empty without it
+    pub(crate) fn attrs(&self) -> &[VertexAttr] {
+        self.vertex_info.map_or(&[], |info| &info.attrs)
+    }
 }
 
 struct ShaderSource<'b> {
@@ -73,8 +158,13 @@ struct ShaderSource<'b> {
 pub struct PipelineBuilder<'a, 'b, B: DeviceBackend> {
     device: &'a mut Device<B>,
     attrs: Vec<VertexAttr>,
+    stride: Option<u32>,
     options: PipelineOptions,
     shaders: Option<ShaderSource<'b>>,
+    includes: Option<&'b HashMap<String, String>>,
+    varyings: Vec<String>,
+    alpha_cutoff: Option<f32>,
+    validate: bool,
 }
 
 impl<'a, 'b, B: DeviceBackend> PipelineBuilder<'a, 'b, B> {
@@ -82,8 +172,13 @@ impl<'a, 'b, B: DeviceBackend> PipelineBuilder<'a, 'b, B> {
         Self {
             device,
             attrs: vec![],
+            stride: None,
             options: Default::default(),
             shaders: None,
+            includes: None,
+            varyings: vec![],
+            alpha_cutoff: None,
+            validate: false,
         }
     }
 
@@ -96,6 +191,9 @@ impl<'a, 'b, B: DeviceBackend> PipelineBuilder<'a, 'b, B> {
     /// Set the vertex structure info for a vertex buffer
     pub fn with_vertex_info(mut self, info: &VertexInfo) -> Self {
         self.attrs.extend(&info.attrs);
+        if info.attrs.iter().any(|attr| attr.slot == 0) {
+            self.stride = info.stride;
+        }
         self
     }
 
@@ -111,19 +209,59 @@ impl<'a, 'b, B: DeviceBackend> PipelineBuilder<'a, 'b, B> {
         self
     }
 
+    /// Blend each color attachment of a MRT pass with its own mode, like accumulating
+    /// one target while replacing another. Needs GLES 3.2 or `OES_draw_buffers_indexed`
+    pub fn with_attachment_blends(mut self, blends: &[BlendMode]) -> Self {
+        self.options.attachment_blends = Some(blends.to_vec());
+        self
+    }
+
     /// Set the Culling mode
     pub fn with_cull_mode(mut self, cull_mode: CullMode) -> Self {
         self.options.cull_mode = cull_mode;
         self
     }
 
-    /// Set the Depth Stencil options
+    /// Set the Depth Stencil options.
+    /// By default the depth test is disabled (`CompareMode::None`) and `write` is true,
+    /// GL doesn't write the depth while the test is disabled
     pub fn with_depth_stencil(mut self, depth_stencil: DepthStencil) -> Self {
         self.options.depth_stencil = depth_stencil;
         self
     }
 
-    /// Set the Color Mask options
+    /// Disable the depth test and the depth writes, for 2D and UI pipelines
+    pub fn without_depth(mut self) -> Self {
+        self.options.depth_stencil = DepthStencil {
+            write: false,
+            compare: CompareMode::None,
+        };
+        self
+    }
+
+    /// Depth only pass filling the depth buffer before the main pass, so the shading
+    /// of the hidden fragments is skipped. Colors aren't written and the test is `Less`
+    pub fn depth_prepass(mut self) -> Self {
+        self.options.color_mask = ColorMask::NONE;
+        self.options.depth_stencil = DepthStencil {
+            write: true,
+            compare: CompareMode::Less,
+        };
+        self
+    }
+
+    /// Main pass after a `depth_prepass`, only the fragments matching the stored depth are drawn.
+    /// The vertex positions must be computed the same way in both pipelines
+    pub fn with_equal_depth(mut self) -> Self {
+        self.options.depth_stencil = DepthStencil {
+            write: false,
+            compare: CompareMode::Equal,
+        };
+        self
+    }
+
+    /// Set the channels written to the color attachments, applied with one `glColorMask`.
+    /// See the `ColorMask` presets, like `ColorMask::ALPHA_ONLY` for accumulation buffers
     pub fn with_color_mask(mut self, color_mask: ColorMask) -> Self {
         self.options.color_mask = color_mask;
         self
@@ -135,12 +273,85 @@ impl<'a, 'b, B: DeviceBackend> PipelineBuilder<'a, 'b, B> {
         self
     }
 
+    /// Enable alpha to coverage, giving dithered edges to alpha tested geometry
+    /// without sorting. It only has an effect when rendering to a MSAA framebuffer
+    pub fn with_alpha_to_coverage(mut self, enabled: bool) -> Self {
+        self.options.alpha_to_coverage = enabled;
+        self
+    }
+
+    /// Run the fragment shader per sample instead of per pixel on MSAA framebuffers,
+    /// smoothing the aliasing inside high frequency shaders. `value` is the fraction of
+    /// the samples shaded, between 0.0 and 1.0. It's a no-op without MSAA
+    pub fn with_min_sample_shading(mut self, value: f32) -> Self {
+        self.options.min_sample_shading = Some(value);
+        self
+    }
+
+    /// Shader chunks used to expand the `#include "name"` lines of the shaders
+    pub fn with_includes(mut self, chunks: &'b HashMap<String, String>) -> Self {
+        self.includes = Some(chunks);
+        self
+    }
+
+    /// Capture these outputs of the vertex shader, interleaved in the same order,
+    /// into the buffer passed to `CommandEncoder::begin_transform_feedback`
+    pub fn with_transform_feedback(mut self, varyings: &[&str]) -> Self {
+        self.varyings = varyings.iter().map(|v| v.to_string()).collect();
+        self
+    }
+
+    /// Emulate the alpha test, discarding the fragments with an alpha lower than `cutoff`.
+    /// The check is added to the fragment shader, after its `main` writes the `out vec4`
+    pub fn with_alpha_test(mut self, cutoff: f32) -> Self {
+        self.alpha_cutoff = Some(cutoff);
+        self
+    }
+
+    /// Check the vertex info against the inputs of the vertex shader before creating the pipeline
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
     /// Build the pipeline with the data set on the builder
     pub fn build(self) -> Result<Pipeline, String> {
         match self.shaders {
             Some(ShaderSource { vertex, fragment }) => {
+                let (vertex, fragment) = match self.includes {
+                    Some(chunks) => (
+                        preprocess_includes(vertex, chunks)?,
+                        preprocess_includes(fragment, chunks)?,
+                    ),
+                    None => (vertex.to_string(), fragment.to_string()),
+                };
+
+                let fragment = match self.alpha_cutoff {
+                    Some(cutoff) => inject_alpha_test(&fragment, cutoff)?,
+                    None => fragment,
+                };
+
+                if self.validate && !self.attrs.is_empty() {
+                    validate_vertex_attrs(&self.attrs, &vertex)?;
+                }
+
                 self.device
-                    .inner_create_pipeline(vertex, fragment, &self.attrs, self.options)
+                    .inner_create_pipeline(
+                        &vertex,
+                        &fragment,
+                        &self.attrs,
+                        self.stride,
+                        &self.varyings,
+                        self.options,
+                    )
+                    .map_err(|err| match self.alpha_cutoff {
+                        // the error lines refer to the generated source, not the user one
+                        Some(_) => format!(
+                            "{}\nFragment shader with the alpha test:\n{}",
+                            err, fragment
+                        ),
+                        None => err,
+                    })
             }
             _ => Err("Vertex and Fragment shaders should be present".to_string()),
         }
@@ -253,8 +464,12 @@ pub enum CompareMode {
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum CullMode {
     None,
+    /// Shadow map passes cull the front faces so the depth is the one of the back faces,
+    /// avoiding the acne without the peter-panning of a big depth bias
     Front,
     Back,
+    /// Cull all the triangles, only points and lines are drawn
+    FrontAndBack,
 }
 
 /// Represents the color mask
@@ -285,12 +500,27 @@ impl ColorMask {
         a: true,
     };
 
+    /// No color writes, for depth or stencil only passes
     pub const NONE: ColorMask = ColorMask {
         r: false,
         g: false,
         b: false,
         a: false,
     };
+
+    pub const RGB_ONLY: ColorMask = ColorMask {
+        r: true,
+        g: true,
+        b: true,
+        a: false,
+    };
+
+    pub const ALPHA_ONLY: ColorMask = ColorMask {
+        r: false,
+        g: false,
+        b: false,
+        a: true,
+    };
 }
 
 /// Represents the color mask
@@ -310,14 +540,23 @@ impl Default for DepthStencil {
 }
 
 /// Options to use with the render pipeline
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PipelineOptions {
     pub color_blend: Option<BlendMode>,
     pub alpha_blend: Option<BlendMode>,
+    /// Blending of each color attachment, in the order of the draw buffers.
+    /// It replaces `color_blend` and `alpha_blend` when there's more than one
+    pub attachment_blends: Option<Vec<BlendMode>>,
     pub cull_mode: CullMode,
     pub depth_stencil: DepthStencil,
     pub color_mask: ColorMask,
     pub stencil: Option<StencilOptions>,
+    /// Use the fragment alpha as the sample coverage mask,
+    /// only has an effect on MSAA framebuffers and is a no-op otherwise
+    pub alpha_to_coverage: bool,
+    /// Fraction of the samples the fragment shader runs for, 1.0 shades every sample.
+    /// Only has an effect on MSAA framebuffers, see `Limits::sample_shading`
+    pub min_sample_shading: Option<f32>,
 }
 
 impl Default for PipelineOptions {
@@ -327,18 +566,30 @@ impl Default for PipelineOptions {
             cull_mode: CullMode::None,
             color_blend: None,
             alpha_blend: None,
+            attachment_blends: None,
             color_mask: Default::default(),
             stencil: None,
+            alpha_to_coverage: false,
+            min_sample_shading: None,
         }
     }
 }
 
-/// Clear options to use at the beginning of the frame
-#[derive(Default, Debug, Clone, Copy, PartialEq)]
+/// Clear options to use at the beginning of the frame,
+/// only the buffers with a value are cleared
+///
+/// Clears are not affected by blending, the color is written as it is.
+/// Layers composited later with a premultiplied alpha pipeline (like `BlendMode::OVER`)
+/// should set `premultiplied` so the clear color matches what the pipeline expects.
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct ClearOptions {
     pub color: Option<Color>,
+    /// Color for each color attachment, applied after `color` using `glClearBufferfv`
+    pub colors: Vec<Color>,
     pub depth: Option<f32>,
     pub stencil: Option<i32>,
+    /// Multiply the rgb values of the clear color by its alpha
+    pub premultiplied: bool,
 }
 
 impl ClearOptions {
@@ -353,6 +604,50 @@ impl ClearOptions {
     pub fn none() -> Self {
         Self::default()
     }
+
+    /// Clear only the depth, keeping the color already rendered to the target.
+    /// Used to draw over the content of the host with an own depth buffer
+    pub fn depth(depth: f32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    /// Create a new struct clearing each color attachment to its own color
+    pub fn colors(colors: Vec<Color>) -> Self {
+        Self {
+            colors,
+            ..Default::default()
+        }
+    }
+
+    /// Clear to the premultiplied alpha version of the color
+    pub fn with_premultiplied(mut self) -> Self {
+        self.premultiplied = true;
+        self
+    }
+
+    /// Color used to clear, taking into account the premultiplied flag
+    pub fn clear_color(&self) -> Option<Color> {
+        if self.premultiplied {
+            self.color.map(|c| c.to_premultiplied_alpha())
+        } else {
+            self.color
+        }
+    }
+
+    /// Colors used to clear each attachment, taking into account the premultiplied flag
+    pub fn clear_colors(&self) -> Vec<Color> {
+        if self.premultiplied {
+            self.colors
+                .iter()
+                .map(|c| c.to_premultiplied_alpha())
+                .collect()
+        } else {
+            self.colors.clone()
+        }
+    }
 }
 
 /// Represents the draw usage
@@ -403,6 +698,8 @@ impl Default for StencilOptions {
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum DrawPrimitive {
+    /// Size set with `gl_PointSize` in the vertex shader
+    Points,
     Lines,
     LineStrip,
     Triangles,
@@ -0,0 +1,10 @@
+/// Id of a gpu query created by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueryId(pub(crate) u64);
+
+impl QueryId {
+    #[inline(always)]
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
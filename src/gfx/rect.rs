@@ -5,3 +5,19 @@ pub struct Rect {
     pub width: f32,
     pub height: f32,
 }
+
+impl Rect {
+    #[inline]
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
+    /// The rect is inside the area of this size starting at the origin
+    #[inline]
+    pub fn is_inside(&self, width: f32, height: f32) -> bool {
+        self.x >= 0.0
+            && self.y >= 0.0
+            && self.x + self.width <= width
+            && self.y + self.height <= height
+    }
+}
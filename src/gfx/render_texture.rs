@@ -1,7 +1,7 @@
 use super::{
     device::{Device, DeviceBackend, DropManager, ResourceId},
     encoder::CommandEncoder,
-    texture::{Texture, TextureFilter, TextureFormat, TextureInfo},
+    texture::{Texture, TextureFilter, TextureFormat, TextureInfo, TextureKind},
 };
 use std::{ops::Deref, sync::Arc};
 
@@ -22,16 +22,26 @@ pub struct RenderTexture {
     id: u64,
     _id_ref: Arc<RenderTextureIdRef>,
     texture: Texture,
+    depth_texture: Option<Texture>,
+    info: TextureInfo,
 }
 
 impl RenderTexture {
-    pub(crate) fn new(id: u64, texture: Texture, drop_manager: Arc<DropManager>) -> Self {
+    pub(crate) fn new(
+        id: u64,
+        texture: Texture,
+        depth_texture: Option<Texture>,
+        info: TextureInfo,
+        drop_manager: Arc<DropManager>,
+    ) -> Self {
         let id_ref = Arc::new(RenderTextureIdRef { id, drop_manager });
 
         Self {
             id,
             _id_ref: id_ref,
             texture,
+            depth_texture,
+            info,
         }
     }
 
@@ -40,12 +50,59 @@ impl RenderTexture {
         self.id
     }
 
+    /// Mip level of the inner texture used as the color attachment
+    #[inline(always)]
+    pub fn mip_level(&self) -> u32 {
+        self.info.mip_level
+    }
+
+    /// Info used to create the render texture
+    #[inline(always)]
+    pub fn info(&self) -> &TextureInfo {
+        &self.info
+    }
+
+    /// No other handle to the render texture or its textures is alive
+    #[inline]
+    pub(crate) fn is_unique(&self) -> bool {
+        Arc::strong_count(&self._id_ref) == 1
+            && self.texture.is_unique()
+            && self.depth_texture.as_ref().is_none_or(|t| t.is_unique())
+    }
+
+    #[inline]
+    pub(crate) fn set_size(&mut self, width: i32, height: i32) {
+        self.info.width = width;
+        self.info.height = height;
+        self.texture.set_size(width, height);
+
+        let (width, height) = self.mip_size();
+        if let Some(depth) = &mut self.depth_texture {
+            depth.set_size(width, height);
+        }
+    }
+
+    /// Size of the attached mip level
+    pub fn mip_size(&self) -> (i32, i32) {
+        let (width, height) = self.texture.base_size();
+        (
+            ((width as i32) >> self.info.mip_level).max(1),
+            ((height as i32) >> self.info.mip_level).max(1),
+        )
+    }
+
     /// Returns a reference to the inner texture
     #[inline(always)]
     pub fn texture(&self) -> &Texture {
         &self.texture
     }
 
+    /// Depth attachment, it can be bound to sample the depth of the pass in a later one
+    #[inline(always)]
+    pub fn depth_texture(&self) -> Option<&Texture> {
+        self.depth_texture.as_ref()
+    }
+
     /// Consume the Render Texture and return the inner texture
     #[inline(always)]
     pub fn take_inner(self) -> Texture {
@@ -55,7 +112,8 @@ impl RenderTexture {
     }
 
     pub fn create_renderer(&mut self) -> CommandEncoder {
-        CommandEncoder::new(self.width() as _, self.height() as _)
+        let (width, height) = self.mip_size();
+        CommandEncoder::new(width, height)
     }
 }
 
@@ -89,6 +147,13 @@ impl<'a, B: DeviceBackend> RenderTextureBuilder<'a, B> {
         self
     }
 
+    /// Enable depth using this format, `Depth16` is used by default
+    pub fn with_depth_format(mut self, format: TextureFormat) -> Self {
+        self.info.depth = true;
+        self.info.depth_format = format;
+        self
+    }
+
     /// Set the Texture format
     pub fn with_format(mut self, format: TextureFormat) -> Self {
         self.info.format = format;
@@ -102,9 +167,75 @@ impl<'a, B: DeviceBackend> RenderTextureBuilder<'a, B> {
         self
     }
 
+    /// Generate the mipmap chain for the inner texture
+    pub fn with_mipmaps(mut self, enable: bool) -> Self {
+        self.info.mipmaps = enable;
+        self
+    }
+
+    /// Use a cubemap as the inner texture, its faces are drawn with `Device::render_cubemap`
+    pub fn with_cubemap(mut self) -> Self {
+        self.info.kind = TextureKind::Cube;
+        self
+    }
+
+    /// Render to this mip level of the inner texture instead of the level 0
+    pub fn with_mip_level(mut self, level: u32) -> Self {
+        self.info.mip_level = level;
+        self
+    }
+
     pub fn build(self) -> Result<RenderTexture, String> {
         let Self { device, info } = self;
 
+        validate_mip_level(&info)?;
+
+        if !info.depth_format.is_depth() {
+            return Err(format!(
+                "{:?} can't be used as a depth format",
+                info.depth_format
+            ));
+        }
+
         device.inner_create_render_texture(info)
     }
 }
+
+/// Info of the depth attachment, sized like the attached mip level
+pub(crate) fn depth_texture_info(info: &TextureInfo) -> Option<TextureInfo> {
+    if !info.depth {
+        return None;
+    }
+
+    Some(TextureInfo {
+        width: (info.width >> info.mip_level).max(1),
+        height: (info.height >> info.mip_level).max(1),
+        format: info.depth_format,
+        min_filter: TextureFilter::Nearest,
+        mag_filter: TextureFilter::Nearest,
+        ..Default::default()
+    })
+}
+
+pub(crate) fn validate_mip_level(info: &TextureInfo) -> Result<(), String> {
+    if info.mip_level == 0 {
+        return Ok(());
+    }
+
+    if !info.mipmaps {
+        return Err(format!(
+            "Cannot render to mip level {} of a texture created without mipmaps",
+            info.mip_level
+        ));
+    }
+
+    let count = info.mip_count();
+    if info.mip_level >= count {
+        return Err(format!(
+            "Invalid mip level {}, the texture only has {} levels",
+            info.mip_level, count
+        ));
+    }
+
+    Ok(())
+}
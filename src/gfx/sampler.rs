@@ -0,0 +1,72 @@
+use super::{
+    device::{DropManager, ResourceId},
+    texture::TextureFilter,
+};
+use std::sync::Arc;
+
+/// Filtering used to sample a texture, independent of the texture's own filters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerInfo {
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    /// Sample between the mip levels, the texture must have mipmaps
+    pub mipmaps: bool,
+}
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        Self {
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mipmaps: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SamplerIdRef {
+    id: u64,
+    drop_manager: Arc<DropManager>,
+}
+
+impl Drop for SamplerIdRef {
+    fn drop(&mut self) {
+        self.drop_manager.push(ResourceId::Sampler(self.id));
+    }
+}
+
+/// Sampler object overriding the filtering of the textures bound with it
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    id: u64,
+    _id_ref: Arc<SamplerIdRef>,
+    info: SamplerInfo,
+}
+
+impl Sampler {
+    pub(crate) fn new(id: u64, info: SamplerInfo, drop_manager: Arc<DropManager>) -> Self {
+        let id_ref = Arc::new(SamplerIdRef { id, drop_manager });
+
+        Self {
+            id,
+            _id_ref: id_ref,
+            info,
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[inline(always)]
+    pub fn info(&self) -> &SamplerInfo {
+        &self.info
+    }
+}
+
+impl std::cmp::PartialEq for Sampler {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
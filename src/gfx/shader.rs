@@ -0,0 +1,324 @@
+use super::buffer::VertexAttr;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Vertex input declared on a shader with `layout(location = N) in <type> <name>;`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderInput {
+    pub location: u32,
+    pub components: i32,
+    pub name: String,
+}
+
+/// Parse the vertex inputs with an explicit location of a GLSL source.
+/// Matrices are expanded to one input per column like the GPU does.
+pub fn vertex_inputs(source: &str) -> Vec<ShaderInput> {
+    strip_comments(source)
+        .split(';')
+        .filter_map(parse_input)
+        .flatten()
+        .collect()
+}
+
+/// Check that every attribute matches an input of the vertex shader
+pub(crate) fn validate_vertex_attrs(attrs: &[VertexAttr], source: &str) -> Result<(), String> {
+    let inputs = vertex_inputs(source);
+
+    let mut errors = vec![];
+    // named attributes get their location when linking, not from the source
+    for attr in attrs.iter().filter(|attr| attr.name.is_none()) {
+        match inputs.iter().find(|input| input.location == attr.location) {
+            Some(input) if input.components != attr.format.size() => errors.push(format!(
+                "Attribute at location {} has {} components but '{}' expects {}",
+                attr.location,
+                attr.format.size(),
+                input.name,
+                input.components
+            )),
+            Some(_) => {}
+            None => errors.push(format!(
+                "Attribute at location {} is not declared by the vertex shader",
+                attr.location
+            )),
+        }
+    }
+
+    for input in &inputs {
+        if !attrs.iter().any(|attr| attr.location == input.location) {
+            errors.push(format!(
+                "Input '{}' at location {} is missing in the vertex info",
+                input.name, input.location
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid vertex info:\n{}", errors.join("\n")))
+    }
+}
+
+/// Replace the `#include "name"` lines with the chunk of the same name,
+/// the chunks can include other chunks
+pub fn preprocess_includes(
+    source: &str,
+    chunks: &HashMap<String, String>,
+) -> Result<String, String> {
+    let mut chain = vec![];
+    expand_includes(source, chunks, &mut chain)
+}
+
+fn expand_includes(
+    source: &str,
+    chunks: &HashMap<String, String>,
+    chain: &mut Vec<String>,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(source.len());
+    for line in source.lines() {
+        let name = match include_name(line) {
+            Some(name) => name,
+            None => {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+        };
+
+        if chain.iter().any(|included| included == name) {
+            return Err(format!(
+                "Recursive shader include: {} -> {}",
+                chain.join(" -> "),
+                name
+            ));
+        }
+
+        let chunk = chunks
+            .get(name)
+            .ok_or_else(|| format!("Shader include '{}' not found", name))?;
+
+        chain.push(name.to_string());
+        output.push_str(&expand_includes(chunk, chunks, chain)?);
+        chain.pop();
+    }
+
+    Ok(output)
+}
+
+/// Discard the fragments with an alpha lower than `cutoff`, renaming the `main`
+/// of the shader and calling it from a new one that tests the `out vec4` output
+pub(crate) fn inject_alpha_test(source: &str, cutoff: f32) -> Result<String, String> {
+    if !cutoff.is_finite() {
+        return Err(format!("Invalid alpha test cutoff: {}", cutoff));
+    }
+
+    let output = fragment_output(source)
+        .ok_or("The alpha test needs an `out vec4` output in the fragment shader")?;
+
+    let name =
+        main_name(source).ok_or("The alpha test needs a `void main()` in the fragment shader")?;
+
+    let mut source = source.to_string();
+    source.replace_range(name, "alpha_test_main");
+    Ok(format!(
+        "{}\nvoid main() {{\n    alpha_test_main();\n    if ({}.a < {:?}) {{\n        discard;\n    }}\n}}\n",
+        source, output, cutoff
+    ))
+}
+
+/// Byte range of the `main` name in the first `void main (...)` of the source
+fn main_name(source: &str) -> Option<Range<usize>> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    source.match_indices("void").find_map(|(start, _)| {
+        if source[..start].chars().next_back().is_some_and(is_ident) {
+            return None;
+        }
+
+        let after = &source[start + "void".len()..];
+        let trimmed = after.trim_start();
+        if trimmed.len() == after.len() {
+            return None;
+        }
+
+        let name_start = source.len() - trimmed.len();
+        let rest = trimmed.strip_prefix("main")?;
+        rest.trim_start()
+            .starts_with('(')
+            .then(|| name_start..name_start + "main".len())
+    })
+}
+
+/// Name of the first `out vec4` declared by a fragment shader
+fn fragment_output(source: &str) -> Option<String> {
+    strip_comments(source).split(';').find_map(|statement| {
+        let mut tokens = statement
+            .split_whitespace()
+            .skip_while(|token| *token != "out")
+            .skip(1)
+            .filter(|token| !matches!(*token, "lowp" | "mediump" | "highp"));
+
+        match (tokens.next(), tokens.next()) {
+            (Some("vec4"), Some(name)) => Some(name.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Name of a `#include "name"` line
+fn include_name(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("#include")?.trim();
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("//") {
+            rest = r.find('\n').map_or("", |i| &r[i..]);
+        } else if let Some(r) = rest.strip_prefix("/*") {
+            rest = r.find("*/").map_or("", |i| &r[i + 2..]);
+        } else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+fn parse_input(statement: &str) -> Option<Vec<ShaderInput>> {
+    let statement = statement.trim();
+    let start = statement.find("layout")?;
+    let statement = &statement[start + "layout".len()..];
+
+    let open = statement.find('(')?;
+    let close = statement.find(')')?;
+    let qualifiers = &statement[open + 1..close];
+    let location = qualifiers.split(',').find_map(|q| {
+        let (key, value) = q.split_once('=')?;
+        (key.trim() == "location")
+            .then(|| value.trim().parse::<u32>().ok())
+            .flatten()
+    })?;
+
+    let mut tokens = statement[close + 1..]
+        .split_whitespace()
+        .filter(|t| !matches!(*t, "flat" | "smooth" | "lowp" | "mediump" | "highp"));
+
+    if tokens.next()? != "in" {
+        return None;
+    }
+
+    let (columns, components) = type_components(tokens.next()?)?;
+    let name = tokens.next()?.to_string();
+
+    Some(
+        (0..columns)
+            .map(|column| ShaderInput {
+                location: location + column,
+                components,
+                name: name.clone(),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the number of locations used and the components of each one
+fn type_components(typ: &str) -> Option<(u32, i32)> {
+    Some(match typ {
+        "float" | "int" | "uint" => (1, 1),
+        "vec2" | "ivec2" | "uvec2" => (1, 2),
+        "vec3" | "ivec3" | "uvec3" => (1, 3),
+        "vec4" | "ivec4" | "uvec4" => (1, 4),
+        "mat2" => (2, 2),
+        "mat3" => (3, 3),
+        "mat4" => (4, 4),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENT: &str = "out vec4 color;\nvoid main() {\n    color = vec4(1.0);\n}\n";
+
+    #[test]
+    fn alpha_test_renames_main() {
+        let source = inject_alpha_test(FRAGMENT, 0.5).unwrap();
+        assert!(source.contains("void alpha_test_main() {"));
+        assert!(source.contains("if (color.a < 0.5) {"));
+        assert_eq!(source.matches("void main()").count(), 1);
+    }
+
+    #[test]
+    fn alpha_test_tolerates_whitespace() {
+        for main in [
+            "void main () {",
+            "void main( void ) {",
+            "void  main\n(void) {",
+        ] {
+            let source = FRAGMENT.replace("void main() {", main);
+            let source = inject_alpha_test(&source, 0.5).unwrap();
+            assert!(source.contains("alpha_test_main"), "{}", main);
+        }
+    }
+
+    #[test]
+    fn alpha_test_rejects_invalid_input() {
+        assert!(inject_alpha_test(FRAGMENT, f32::NAN).is_err());
+        assert!(inject_alpha_test(FRAGMENT, f32::INFINITY).is_err());
+        assert!(inject_alpha_test("out vec4 color;\nvoid mainly() {}\n", 0.5).is_err());
+        assert!(inject_alpha_test("void main() {}\n", 0.5).is_err());
+    }
+
+    #[test]
+    fn includes_are_expanded() {
+        let chunks: HashMap<String, String> = [
+            ("a".to_string(), "#include \"b\"\nfloat a;".to_string()),
+            ("b".to_string(), "float b;".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let source = preprocess_includes("#include \"a\"\nvoid main() {}", &chunks).unwrap();
+        assert_eq!(source, "float b;\nfloat a;\nvoid main() {}\n");
+        assert!(preprocess_includes("#include \"c\"", &chunks).is_err());
+    }
+
+    #[test]
+    fn recursive_includes_are_rejected() {
+        let chunks: HashMap<String, String> = [
+            ("a".to_string(), "#include \"b\"".to_string()),
+            ("b".to_string(), "#include \"a\"".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let err = preprocess_includes("#include \"a\"", &chunks).unwrap_err();
+        assert_eq!(err, "Recursive shader include: a -> b -> a");
+    }
+
+    #[test]
+    fn vertex_inputs_expand_matrices() {
+        let source = "layout(location = 0) in vec3 a_pos;\n\
+                      // layout(location = 1) in vec2 a_uv;\n\
+                      layout(location = 2) in highp mat3 a_model;\n\
+                      layout(std140) uniform Locals { vec4 tint; };";
+
+        let names: Vec<_> = vertex_inputs(source)
+            .into_iter()
+            .map(|input| (input.location, input.components, input.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (0, 3, "a_pos".to_string()),
+                (2, 3, "a_model".to_string()),
+                (3, 3, "a_model".to_string()),
+                (4, 3, "a_model".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use super::pipeline::DrawPrimitive;
+use std::time::Duration;
+
+/// Counters of the work sent to the gpu during a frame, a frame ends on `swap_buffers`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub draw_calls: usize,
+    pub instanced_draws: usize,
+    pub triangles: usize,
+    pub buffer_uploads: usize,
+    /// CPU time recording the passes of the encoders given to `Device::submit`
+    pub encode_time: Duration,
+    /// CPU time spent by `render` and `render_to` sending the commands to the driver
+    pub submit_time: Duration,
+}
+
+impl FrameStats {
+    #[inline]
+    pub(crate) fn add_draw(&mut self, primitive: &DrawPrimitive, count: i32) {
+        self.draw_calls += 1;
+        self.triangles += triangles(primitive, count);
+    }
+
+    #[inline]
+    pub(crate) fn add_instanced_draw(
+        &mut self,
+        primitive: &DrawPrimitive,
+        count: i32,
+        length: i32,
+    ) {
+        self.instanced_draws += 1;
+        self.triangles += triangles(primitive, count) * length.max(0) as usize;
+    }
+}
+
+#[inline]
+fn triangles(primitive: &DrawPrimitive, count: i32) -> usize {
+    let count = count.max(0) as usize;
+    match primitive {
+        DrawPrimitive::Triangles => count / 3,
+        DrawPrimitive::TriangleStrip => count.saturating_sub(2),
+        DrawPrimitive::Points | DrawPrimitive::Lines | DrawPrimitive::LineStrip => 0,
+    }
+}
@@ -0,0 +1,232 @@
+use super::{
+    buffer::{Buffer, VertexFormat, VertexInfo},
+    color::Color,
+    device::{Device, DeviceBackend},
+    draw2d::flush_pixel_triangles,
+    encoder::CommandEncoder,
+    pipeline::{BlendMode, Pipeline},
+    rect::Rect,
+    texture::{Texture, TextureFilter, TextureFormat},
+};
+use std::collections::HashMap;
+
+const VERT: &str = r#"
+    #version 310 es
+    layout(location = 0) in vec2 a_pos;
+    layout(location = 1) in vec2 a_uv;
+    layout(location = 2) in vec4 a_color;
+
+    layout(location = 0) out vec2 v_uv;
+    layout(location = 1) out vec4 v_color;
+
+    void main() {
+        v_uv = a_uv;
+        v_color = a_color;
+        gl_Position = vec4(a_pos, 0.0, 1.0);
+    }
+"#;
+
+const FRAG: &str = r#"
+    #version 310 es
+    precision mediump float;
+
+    layout(location = 0) in vec2 v_uv;
+    layout(location = 1) in vec4 v_color;
+    layout(location = 0) out vec4 color;
+
+    uniform sampler2D u_atlas;
+
+    void main() {
+        color = vec4(v_color.rgb, v_color.a * texture(u_atlas, v_uv).r);
+    }
+"#;
+
+/// Floats per vertex: position xy + uv + color rgba
+const VERTEX_SIZE: usize = 8;
+
+/// Monospaced bitmap font with the glyphs of consecutive chars starting at `first_char`.
+/// Each glyph is `glyph_width * glyph_height` coverage bytes, stored one after another
+#[derive(Debug, Clone)]
+pub struct BitmapFont {
+    pub glyph_width: i32,
+    pub glyph_height: i32,
+    pub first_char: char,
+    pub pixels: Vec<u8>,
+}
+
+impl BitmapFont {
+    /// Number of glyphs in the pixels
+    pub fn glyph_count(&self) -> usize {
+        let glyph_size = (self.glyph_width * self.glyph_height).max(1) as usize;
+        self.pixels.len() / glyph_size
+    }
+}
+
+/// Draws strings with the glyphs of a bitmap font packed in an R8 atlas texture,
+/// batched like `draw2d::Batch` in one draw call on `flush`.
+///
+/// Positions are in pixels with the origin at the top-left of the target,
+/// `x, y` is the top-left corner of the first glyph.
+pub struct TextRenderer {
+    pipeline: Pipeline,
+    vbo: Buffer,
+    atlas: Texture,
+    glyphs: HashMap<char, Rect>,
+    glyph_size: (f32, f32),
+    vertices: Vec<f32>,
+    upload: Vec<f32>,
+}
+
+impl TextRenderer {
+    pub fn new<B: DeviceBackend>(
+        device: &mut Device<B>,
+        font: &BitmapFont,
+    ) -> Result<Self, String> {
+        if font.glyph_width <= 0 || font.glyph_height <= 0 {
+            return Err(format!(
+                "Invalid glyph size {}x{}",
+                font.glyph_width, font.glyph_height
+            ));
+        }
+
+        let count = font.glyph_count();
+        if count == 0 {
+            return Err("The bitmap font doesn't have any glyph".to_string());
+        }
+
+        // square-ish grid of glyphs
+        let columns = (count as f32).sqrt().ceil() as usize;
+        let rows = count.div_ceil(columns);
+        let (gw, gh) = (font.glyph_width as usize, font.glyph_height as usize);
+        let (width, height) = (columns * gw, rows * gh);
+
+        let mut pixels = vec![0; width * height];
+        let mut glyphs = HashMap::with_capacity(count);
+        for (i, glyph) in font.pixels.chunks_exact(gw * gh).enumerate() {
+            let (x, y) = ((i % columns) * gw, (i / columns) * gh);
+            glyph.chunks_exact(gw).enumerate().for_each(|(row, line)| {
+                let start = (y + row) * width + x;
+                pixels[start..start + gw].copy_from_slice(line);
+            });
+
+            if let Some(c) = char::from_u32(font.first_char as u32 + i as u32) {
+                glyphs.insert(
+                    c,
+                    Rect {
+                        x: x as f32 / width as f32,
+                        y: y as f32 / height as f32,
+                        width: gw as f32 / width as f32,
+                        height: gh as f32 / height as f32,
+                    },
+                );
+            }
+        }
+
+        let atlas = device
+            .create_texture()
+            .from_bytes(&pixels, width as _, height as _)
+            .with_format(TextureFormat::R8)
+            .with_filter(TextureFilter::Nearest, TextureFilter::Nearest)
+            .build()?;
+
+        let vertex_info = VertexInfo::new()
+            .attr(0, VertexFormat::Float32x2)
+            .attr(1, VertexFormat::Float32x2)
+            .attr(2, VertexFormat::Float32x4);
+
+        let pipeline = device
+            .create_pipeline()
+            .from(VERT, FRAG)
+            .with_vertex_info(&vertex_info)
+            .with_color_blend(BlendMode::NORMAL)
+            .build()?;
+
+        let vbo = device
+            .create_vertex_buffer()
+            .with_info(&vertex_info)
+            .build()?;
+
+        Ok(Self {
+            pipeline,
+            vbo,
+            atlas,
+            glyphs,
+            glyph_size: (gw as f32, gh as f32),
+            vertices: vec![],
+            upload: vec![],
+        })
+    }
+
+    /// R8 texture with the glyphs
+    pub fn atlas(&self) -> &Texture {
+        &self.atlas
+    }
+
+    /// Size in pixels of the text, `\n` starts a new line
+    pub fn measure(&self, text: &str) -> (f32, f32) {
+        let (gw, gh) = self.glyph_size;
+        let lines = text.split('\n');
+        let (count, columns) = lines.fold((0, 0), |(count, columns), line| {
+            (count + 1, columns.max(line.chars().count()))
+        });
+        (columns as f32 * gw, count as f32 * gh)
+    }
+
+    /// Add the quads of the text, chars missing in the font leave a blank space
+    pub fn draw(&mut self, text: &str, x: f32, y: f32, color: Color) {
+        let (gw, gh) = self.glyph_size;
+        let (mut pen_x, mut pen_y) = (x, y);
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                pen_y += gh;
+                continue;
+            }
+
+            if let Some(uv) = self.glyphs.get(&c).copied() {
+                self.quad(pen_x, pen_y, uv, color);
+            }
+            pen_x += gw;
+        }
+    }
+
+    /// Upload the text and record the draw on the encoder, the renderer is empty after it
+    pub fn flush<B: DeviceBackend>(
+        &mut self,
+        device: &mut Device<B>,
+        encoder: &mut CommandEncoder,
+    ) {
+        flush_pixel_triangles(
+            device,
+            encoder,
+            &self.vertices,
+            VERTEX_SIZE,
+            &mut self.upload,
+            &self.vbo,
+            |encoder| {
+                encoder.set_pipeline(&self.pipeline);
+                encoder.bind_buffer(&self.vbo);
+                encoder.bind_texture(0, &self.atlas);
+            },
+        );
+
+        self.vertices.clear();
+    }
+
+    fn quad(&mut self, x: f32, y: f32, uv: Rect, color: Color) {
+        let (gw, gh) = self.glyph_size;
+        let (u0, v0, u1, v1) = (uv.x, uv.y, uv.x + uv.width, uv.y + uv.height);
+        let corners = [
+            (x, y, u0, v0),
+            (x + gw, y, u1, v0),
+            (x + gw, y + gh, u1, v1),
+            (x, y + gh, u0, v1),
+        ];
+
+        [0, 1, 2, 0, 2, 3].iter().for_each(|i| {
+            let (px, py, u, v) = corners[*i];
+            self.vertices
+                .extend_from_slice(&[px, py, u, v, color.r, color.g, color.b, color.a]);
+        });
+    }
+}
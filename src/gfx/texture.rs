@@ -6,6 +6,9 @@ use super::{
     rect::Rect,
 };
 
+/// Number of texture slots that can be used to bind textures
+pub const MAX_TEXTURE_SLOTS: u32 = 8;
+
 #[derive(Debug)]
 pub struct TextureRead {
     pub x_offset: i32,
@@ -23,6 +26,10 @@ pub struct TextureUpdate<'a> {
     pub height: i32,
     pub format: TextureFormat,
     pub bytes: &'a [u8],
+    /// Pixels per row of `bytes`, when it's a crop of a larger image
+    pub row_length: Option<i32>,
+    /// Layer updated on array textures, or the `CubeFace` of cubemaps
+    pub layer: u32,
 }
 
 #[derive(Clone, Debug)]
@@ -34,9 +41,23 @@ pub struct TextureInfo {
     pub mag_filter: TextureFilter,
     pub bytes: Option<Vec<u8>>,
     pub premultiplied_alpha: bool,
+    pub mipmaps: bool,
+    /// First mip level used when sampling
+    pub base_level: u32,
+    /// Last mip level used when sampling, `None` uses the full chain
+    pub max_level: Option<u32>,
+    /// Channel returned for each of the r, g, b, a components when sampled
+    pub swizzle: [Swizzle; 4],
+    pub kind: TextureKind,
+    /// Immutable storage, needed to bind the texture with `bind_image_texture`
+    pub image_storage: bool,
 
     /// Used for render textures
     pub depth: bool,
+    /// Format of the depth attachment of render textures
+    pub depth_format: TextureFormat,
+    /// Mip level attached when used as a render texture
+    pub mip_level: u32,
 }
 
 impl Default for TextureInfo {
@@ -49,18 +70,47 @@ impl Default for TextureInfo {
             height: 1,
             bytes: None,
             depth: false,
+            depth_format: TextureFormat::Depth16,
             premultiplied_alpha: false,
+            mipmaps: false,
+            base_level: 0,
+            max_level: None,
+            swizzle: Swizzle::IDENTITY,
+            kind: TextureKind::Texture2D,
+            image_storage: false,
+            mip_level: 0,
         }
     }
 }
 
 impl TextureInfo {
     pub fn bytes_per_pixel(&self) -> u8 {
-        match self.format {
-            TextureFormat::R8 => 1,
-            TextureFormat::Depth16 => 2,
-            TextureFormat::Rgba32 => 4,
+        self.format.bytes_per_pixel()
+    }
+
+    /// Number of layers, 1 for 2d textures
+    pub fn layers(&self) -> u32 {
+        match self.kind {
+            TextureKind::Texture2D => 1,
+            TextureKind::Array { layers } => layers,
+            TextureKind::Cube => 6,
+        }
+    }
+
+    /// Number of mip levels the texture has, 1 if mipmaps are disabled
+    pub fn mip_count(&self) -> u32 {
+        if !self.mipmaps {
+            return 1;
         }
+
+        let size = self.width.max(self.height).max(1) as u32;
+        u32::BITS - size.leading_zeros()
+    }
+
+    /// Range of mip levels used when sampling, clamped by `base_level` and `max_level`
+    pub fn mip_levels(&self) -> (u32, u32) {
+        let last = self.mip_count() - 1;
+        (self.base_level, self.max_level.unwrap_or(last))
     }
 }
 
@@ -85,6 +135,7 @@ pub struct Texture {
     format: TextureFormat,
     min_filter: TextureFilter,
     mag_filter: TextureFilter,
+    kind: TextureKind,
     frame: Rect,
 }
 
@@ -99,6 +150,7 @@ impl Texture {
             format,
             min_filter,
             mag_filter,
+            kind,
             ..
         } = info;
 
@@ -117,6 +169,7 @@ impl Texture {
             format,
             min_filter,
             mag_filter,
+            kind,
             frame,
         }
     }
@@ -141,6 +194,11 @@ impl Texture {
         &self.mag_filter
     }
 
+    #[inline(always)]
+    pub fn kind(&self) -> &TextureKind {
+        &self.kind
+    }
+
     #[inline(always)]
     pub fn frame(&self) -> &Rect {
         &self.frame
@@ -187,6 +245,24 @@ impl Texture {
     pub fn base_size(&self) -> (f32, f32) {
         (self.width as _, self.height as _)
     }
+
+    /// No other handle to the texture is alive
+    #[inline]
+    pub(crate) fn is_unique(&self) -> bool {
+        Arc::strong_count(&self._id_ref) == 1
+    }
+
+    #[inline]
+    pub(crate) fn set_size(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.frame = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: width as _,
+            height: height as _,
+        };
+    }
 }
 
 impl std::cmp::PartialEq for Texture {
@@ -201,11 +277,140 @@ impl AsRef<Texture> for Texture {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum TextureFormat {
     Rgba32,
     R8,
     Depth16,
+    /// 24 bits depth, stored in 4 bytes
+    Depth24,
+    /// Float depth, for high precision shadow maps
+    Depth32F,
+    /// Half float red channel, the bytes are `f16` values
+    R16F,
+    /// Half float rgba for HDR targets, the bytes are `f16` values.
+    /// Rendering to float formats needs `EXT_color_buffer_float` on GLES
+    Rgba16F,
+    /// Float rgba, the bytes are `f32` values
+    Rgba32F,
+    /// ETC2 compressed rgb, 8 bytes for each block of 4x4 pixels. Always available on GLES 3
+    Etc2Rgb8,
+    /// ASTC compressed rgba, 16 bytes for each block of 4x4 pixels.
+    /// Needs `KHR_texture_compression_astc_ldr`
+    Astc4x4,
+}
+
+impl TextureFormat {
+    /// Bytes of one pixel, 0 for the compressed formats that are stored by blocks
+    pub fn bytes_per_pixel(&self) -> u8 {
+        match self {
+            TextureFormat::Etc2Rgb8 | TextureFormat::Astc4x4 => 0,
+            TextureFormat::R8 => 1,
+            TextureFormat::Depth16 => 2,
+            TextureFormat::Depth24 | TextureFormat::Depth32F => 4,
+            TextureFormat::R16F => 2,
+            TextureFormat::Rgba32 => 4,
+            TextureFormat::Rgba16F => 8,
+            TextureFormat::Rgba32F => 16,
+        }
+    }
+
+    /// Format stored in blocks of 4x4 pixels
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, TextureFormat::Etc2Rgb8 | TextureFormat::Astc4x4)
+    }
+
+    /// Bytes of a compressed image of this size, `None` for uncompressed formats
+    pub fn compressed_size(&self, width: i32, height: i32) -> Option<usize> {
+        let block_bytes = match self {
+            TextureFormat::Etc2Rgb8 => 8,
+            TextureFormat::Astc4x4 => 16,
+            _ => return None,
+        };
+
+        let blocks_x = (width.max(1) as usize).div_ceil(4);
+        let blocks_y = (height.max(1) as usize).div_ceil(4);
+        Some(blocks_x * blocks_y * block_bytes)
+    }
+
+    /// Format that a compute shader can read and write as an image (`imageLoad`, `imageStore`)
+    pub fn is_image_storable(&self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Rgba32 | TextureFormat::Rgba16F | TextureFormat::Rgba32F
+        )
+    }
+
+    /// Format used for depth attachments
+    pub fn is_depth(&self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TextureKind {
+    Texture2D,
+    /// `TEXTURE_2D_ARRAY` with the layers stacked in the bytes one after another
+    Array {
+        layers: u32,
+    },
+    /// `TEXTURE_CUBE_MAP` with the faces stacked in the bytes in the `CubeFace` order
+    Cube,
+}
+
+/// Faces of a cubemap, in the order of the `TEXTURE_CUBE_MAP_POSITIVE_X + n` targets
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// Column-major view from the origin looking at the face, with the up vectors of the
+    /// cubemap convention. Use it with a 90 degrees projection with an aspect ratio of 1
+    pub fn view_matrix(&self) -> [f32; 16] {
+        let (dir, up): ([f32; 3], [f32; 3]) = match self {
+            CubeFace::PositiveX => ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            CubeFace::NegativeX => ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            CubeFace::PositiveY => ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            CubeFace::NegativeY => ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+            CubeFace::PositiveZ => ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+            CubeFace::NegativeZ => ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        };
+
+        // right handed look at, the axes are unit vectors so nothing needs normalizing
+        let cross = |a: [f32; 3], b: [f32; 3]| {
+            [
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            ]
+        };
+        let side = cross(dir, up);
+        let up = cross(side, dir);
+        [
+            side[0], up[0], -dir[0], 0.0, //
+            side[1], up[1], -dir[1], 0.0, //
+            side[2], up[2], -dir[2], 0.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ]
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -214,14 +419,52 @@ pub enum TextureFilter {
     Nearest,
 }
 
-enum TextureKind<'a> {
+/// How a shader accesses a texture bound as an image
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ImageAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Quality of the mipmaps generated for the textures
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MipmapHint {
+    Fastest,
+    Nicest,
+    DontCare,
+}
+
+/// Source channel used for a texture component when sampled
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Swizzle {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Zero,
+    One,
+}
+
+impl Swizzle {
+    pub const IDENTITY: [Swizzle; 4] =
+        [Swizzle::Red, Swizzle::Green, Swizzle::Blue, Swizzle::Alpha];
+
+    /// Replicate the red channel, useful to sample R8 masks as `.rrrr`
+    pub const RED_TO_ALL: [Swizzle; 4] = [Swizzle::Red, Swizzle::Red, Swizzle::Red, Swizzle::Red];
+
+    /// White color with the red channel as alpha, useful to sample R8 masks as `.a`
+    pub const RED_TO_ALPHA: [Swizzle; 4] = [Swizzle::One, Swizzle::One, Swizzle::One, Swizzle::Red];
+}
+
+enum TextureSource<'a> {
     Bytes(&'a [u8]),
     EmptyBuffer,
 }
 
 pub struct TextureBuilder<'a, 'b, B: DeviceBackend> {
     device: &'a mut Device<B>,
-    kind: Option<TextureKind<'b>>,
+    source: Option<TextureSource<'b>>,
     info: TextureInfo,
 }
 
@@ -230,14 +473,14 @@ impl<'a, 'b, B: DeviceBackend> TextureBuilder<'a, 'b, B> {
         Self {
             device,
             info: Default::default(),
-            kind: None,
+            source: None,
         }
     }
 
     /// Creates a Texture from a buffer of pixels
     #[allow(clippy::wrong_self_convention)]
     pub fn from_bytes(mut self, bytes: &'b [u8], width: i32, height: i32) -> Self {
-        self.kind = Some(TextureKind::Bytes(bytes));
+        self.source = Some(TextureSource::Bytes(bytes));
         self.info.width = width;
         self.info.height = height;
         self
@@ -246,7 +489,7 @@ impl<'a, 'b, B: DeviceBackend> TextureBuilder<'a, 'b, B> {
     /// Creates a buffer for the size passed in and creates a Texture with it
     #[allow(clippy::wrong_self_convention)]
     pub fn from_empty_buffer(mut self, width: i32, height: i32) -> Self {
-        self.kind = Some(TextureKind::EmptyBuffer);
+        self.source = Some(TextureSource::EmptyBuffer);
         self.with_size(width, height)
     }
 
@@ -282,19 +525,104 @@ impl<'a, 'b, B: DeviceBackend> TextureBuilder<'a, 'b, B> {
         self
     }
 
+    /// Generate the mipmap chain for the texture
+    pub fn with_mipmaps(mut self, enable: bool) -> Self {
+        self.info.mipmaps = enable;
+        self
+    }
+
+    /// Sample only the mip levels from `base` to `max`,
+    /// to clamp the chain or stream in the levels progressively
+    pub fn with_mip_levels(mut self, base: u32, max: u32) -> Self {
+        self.info.base_level = base;
+        self.info.max_level = Some(max);
+        self
+    }
+
+    /// Creates a texture array with this number of layers of the texture size
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.info.kind = TextureKind::Array { layers };
+        self
+    }
+
+    /// Allocate immutable storage so compute shaders can write the texture
+    /// as an image, the format must be image storable
+    pub fn with_image_storage(mut self) -> Self {
+        self.info.image_storage = true;
+        self
+    }
+
+    /// Creates a cubemap with square faces of the texture size
+    pub fn with_cubemap(mut self) -> Self {
+        self.info.kind = TextureKind::Cube;
+        self
+    }
+
+    /// Set the channels returned for r, g, b, a when the texture is sampled
+    pub fn with_swizzle(mut self, swizzle: [Swizzle; 4]) -> Self {
+        self.info.swizzle = swizzle;
+        self
+    }
+
     pub fn build(self) -> Result<Texture, String> {
         let TextureBuilder {
             mut info,
             device,
-            kind,
+            source,
         } = self;
 
-        match kind {
-            Some(TextureKind::Bytes(bytes)) => {
+        let (base, max) = info.mip_levels();
+        if base > max || max >= info.mip_count() {
+            return Err(format!(
+                "Invalid mip levels {}..={} for a texture with {} levels",
+                base,
+                max,
+                info.mip_count()
+            ));
+        }
+
+        if info.image_storage && !info.format.is_image_storable() {
+            return Err(format!(
+                "{:?} textures can't be bound as images",
+                info.format
+            ));
+        }
+
+        if let Some(size) = info.format.compressed_size(info.width, info.height) {
+            let bytes = match source {
+                Some(TextureSource::Bytes(bytes)) => bytes,
+                _ => return Err("Compressed textures must be created from bytes".to_string()),
+            };
+
+            let size = size * info.layers() as usize;
+            if bytes.len() != size {
+                return Err(format!(
+                    "Compressed {:?} texture of len {} when it should be {}",
+                    info.format,
+                    bytes.len(),
+                    size
+                ));
+            }
+
+            // the mipmaps of compressed formats can't be generated by the gpu
+            if info.mipmaps || info.premultiplied_alpha {
+                return Err(format!(
+                    "Compressed {:?} textures can't generate mipmaps or premultiply the alpha",
+                    info.format
+                ));
+            }
+
+            info.bytes = Some(bytes.to_vec());
+            return device.inner_create_texture(info);
+        }
+
+        match source {
+            Some(TextureSource::Bytes(bytes)) => {
                 #[cfg(debug_assertions)]
                 {
-                    let size = info.width * info.height * 4;
-                    debug_assert_eq!(bytes.len(), size as usize, "Texture bytes of len {} when it should be {} (width: {} * height: {} * bytes: {})", bytes.len(), size, info.width, info.height, 4);
+                    let bpp = info.bytes_per_pixel() as i32;
+                    let size = info.width * info.height * bpp * info.layers() as i32;
+                    debug_assert_eq!(bytes.len(), size as usize, "Texture bytes of len {} when it should be {} (width: {} * height: {} * bytes: {} * layers: {})", bytes.len(), size, info.width, info.height, bpp, info.layers());
                 }
 
                 let pixels = if info.premultiplied_alpha {
@@ -305,8 +633,11 @@ impl<'a, 'b, B: DeviceBackend> TextureBuilder<'a, 'b, B> {
 
                 info.bytes = Some(pixels);
             }
-            Some(TextureKind::EmptyBuffer) => {
-                let size = info.width * info.height * (info.bytes_per_pixel() as i32);
+            Some(TextureSource::EmptyBuffer) => {
+                let size = info.width
+                    * info.height
+                    * (info.bytes_per_pixel() as i32)
+                    * info.layers() as i32;
                 info.bytes = Some(vec![0; size as _]);
             }
             _ => {}
@@ -380,7 +711,37 @@ impl<'a, B: DeviceBackend> TextureReader<'a, B> {
         self
     }
 
+    /// Bytes needed to read the pixels, `width * height * bytes_per_pixel` of the format
+    pub fn len(&self) -> usize {
+        (self.width * self.height) as usize * self.format.bytes_per_pixel() as usize
+    }
+
+    /// Read the pixels into the vec, resized to `len` reusing its capacity
+    /// so repeated reads don't allocate
+    pub fn read_into(self, bytes: &mut Vec<u8>) -> Result<(), String> {
+        bytes.resize(self.len(), 0);
+        self.read_to(bytes)
+    }
+
     pub fn read_to(self, bytes: &mut [u8]) -> Result<(), String> {
+        if self.format.is_compressed() {
+            return Err(format!(
+                "Cannot read the pixels of a {:?} texture",
+                self.format
+            ));
+        }
+
+        let len = self.len();
+        if bytes.len() < len {
+            return Err(format!(
+                "Reading {}x{} pixels needs {} bytes but the slice has {}",
+                self.width,
+                self.height,
+                len,
+                bytes.len()
+            ));
+        }
+
         let Self {
             device,
             texture,
@@ -412,6 +773,9 @@ pub struct TextureUpdater<'a, B: DeviceBackend> {
     height: i32,
     format: TextureFormat,
     bytes: Option<&'a [u8]>,
+    row_length: Option<i32>,
+    layer: u32,
+    region: Option<Rect>,
 }
 
 impl<'a, B: DeviceBackend> TextureUpdater<'a, B> {
@@ -431,9 +795,24 @@ impl<'a, B: DeviceBackend> TextureUpdater<'a, B> {
             height,
             format,
             bytes: None,
+            row_length: None,
+            layer: 0,
+            region: None,
         }
     }
 
+    /// Update the pixels of the rect with tightly packed bytes (or rows of `with_row_length`),
+    /// the rect must use whole pixels, be inside the texture and the bytes must cover its area
+    pub fn region(mut self, rect: Rect, bytes: &'a [u8]) -> Self {
+        self.x_offset = rect.x as _;
+        self.y_offset = rect.y as _;
+        self.width = rect.width as _;
+        self.height = rect.height as _;
+        self.bytes = Some(bytes);
+        self.region = Some(rect);
+        self
+    }
+
     /// Update pixels from the axis x offset
     pub fn with_x_offset(mut self, offset: i32) -> Self {
         self.x_offset = offset;
@@ -463,6 +842,19 @@ impl<'a, B: DeviceBackend> TextureUpdater<'a, B> {
         self
     }
 
+    /// Number of pixels of each row in the data, used to upload a region
+    /// of a larger image without copying it to a contiguous buffer first
+    pub fn with_row_length(mut self, row_length: i32) -> Self {
+        self.row_length = Some(row_length);
+        self
+    }
+
+    /// Layer to update of an array texture, or the `CubeFace` index of a cubemap
+    pub fn with_layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
     pub fn update(self) -> Result<(), String> {
         let Self {
             device,
@@ -473,11 +865,77 @@ impl<'a, B: DeviceBackend> TextureUpdater<'a, B> {
             height,
             format,
             bytes,
+            row_length,
+            layer,
+            region,
         } = self;
 
         let bytes =
             bytes.ok_or_else(|| "You need to provide bytes to update a texture".to_string())?;
 
+        if let Some(rect) = region {
+            let whole = |v: f32| v.fract() == 0.0;
+            if !(whole(rect.x) && whole(rect.y) && whole(rect.width) && whole(rect.height)) {
+                return Err(format!("Region {:?} must use whole pixels", rect));
+            }
+
+            // checked on the final values, the other builder methods can change them
+            let (tex_width, tex_height) = texture.base_size();
+            let (tex_width, tex_height) = (tex_width as i32, tex_height as i32);
+            if x_offset < 0
+                || y_offset < 0
+                || width <= 0
+                || height <= 0
+                || x_offset + width > tex_width
+                || y_offset + height > tex_height
+            {
+                return Err(format!(
+                    "Region {}x{} at {},{} is out of the texture of size {}x{}",
+                    width, height, x_offset, y_offset, tex_width, tex_height
+                ));
+            }
+
+            let row = row_length.unwrap_or(width);
+            if row < width {
+                return Err(format!(
+                    "Row length {} is smaller than the region width {}",
+                    row, width
+                ));
+            }
+
+            // the last row only needs the pixels of the region
+            let pixels = row as usize * (height as usize - 1) + width as usize;
+            let size = pixels * format.bytes_per_pixel() as usize;
+            let valid = match row_length {
+                Some(_) => bytes.len() >= size,
+                None => bytes.len() == size,
+            };
+            if !valid {
+                return Err(format!(
+                    "Region {}x{} needs {} bytes but {} were given",
+                    width,
+                    height,
+                    size,
+                    bytes.len()
+                ));
+            }
+        }
+
+        let layers = match texture.kind {
+            TextureKind::Texture2D => None,
+            TextureKind::Array { layers } => Some(layers),
+            TextureKind::Cube => Some(6),
+        };
+
+        if let Some(layers) = layers {
+            if layer >= layers {
+                return Err(format!(
+                    "Layer {} is out of the {} layers of the texture",
+                    layer, layers
+                ));
+            }
+        }
+
         let info = TextureUpdate {
             x_offset,
             y_offset,
@@ -485,8 +943,32 @@ impl<'a, B: DeviceBackend> TextureUpdater<'a, B> {
             height,
             format,
             bytes,
+            row_length,
+            layer,
         };
 
         device.inner_update_texture(texture, &info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn cube_face_view_matches_look_at() {
+        let expected = [
+            (Vec3::X, -Vec3::Y),
+            (-Vec3::X, -Vec3::Y),
+            (Vec3::Y, Vec3::Z),
+            (-Vec3::Y, -Vec3::Z),
+            (Vec3::Z, -Vec3::Y),
+            (-Vec3::Z, -Vec3::Y),
+        ];
+        for (face, (dir, up)) in CubeFace::ALL.iter().zip(expected) {
+            let view = Mat4::look_at_rh(Vec3::ZERO, dir, up).to_cols_array();
+            assert_eq!(face.view_matrix(), view, "{:?}", face);
+        }
+    }
+}
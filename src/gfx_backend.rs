@@ -1,31 +1,50 @@
 use self::{
-    buffer::{InnerBuffer, Kind},
-    pipeline::{get_inner_attrs, InnerPipeline, VertexAttributes},
-    render_target::InnerRenderTexture,
-    texture::{texture_format, InnerTexture},
-    to_gl::ToGl,
+    buffer::{InnerBuffer, Kind, ShaderStorageBlockBindingFn},
+    pipeline::{
+        get_inner_attrs, IndexedBlendFns, InnerComputePipeline, InnerPipeline, MinSampleShadingFn,
+        PipelineFns, VertexAttributes,
+    },
+    render_target::{is_renderable, InnerRenderTexture},
+    texture::{
+        compressed_formats, create_sampler, image_format, is_readable, read_pixels_converted,
+        texture_format, texture_internal_format, texture_type, InnerTexture,
+    },
+    to_gl::{ToGl, ToOptionalGl},
 };
 use crate::{
     gfx::{
-        buffer::{VertexAttr, VertexStepMode},
+        barrier::BarrierFlags,
+        buffer::{IndexFormat, VertexAttr, VertexStepMode},
         color::Color,
-        commands::Commands,
+        commands::{Commands, UniformValue},
         device::{DeviceBackend, ResourceId},
-        limits::Limits,
-        pipeline::{DrawPrimitive, PipelineOptions},
-        texture::{TextureInfo, TextureRead, TextureUpdate},
+        limits::{BackendInfo, Limits},
+        pipeline::{
+            AttributeInfo, CompareMode, DrawPrimitive, PipelineDesc, PipelineOptions,
+            StencilOptions,
+        },
+        rect::Rect,
+        render_texture::depth_texture_info,
+        sampler::SamplerInfo,
+        stats::FrameStats,
+        texture::{
+            CubeFace, ImageAccess, MipmapHint, TextureFilter, TextureFormat, TextureInfo,
+            TextureRead, TextureUpdate, MAX_TEXTURE_SLOTS,
+        },
     },
-    gfx_backend::gl::types::GLint,
+    gfx_backend::gl::types::{GLint, GLsync},
+};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{c_void, CString},
+    time::Duration,
 };
-use std::collections::HashMap;
 use winit::window::Window;
 
 #[cfg(target_os = "linux")]
 use egl::{EGLContext, EGLDisplay, EGLSurface};
 
-#[cfg(target_os = "linux")]
-use winit::platform::unix::WindowExtUnix;
-
 mod buffer;
 pub mod gl;
 mod pipeline;
@@ -51,6 +70,29 @@ static CONFIG_ATTRIBS: &[i32] = &[
     egl::EGL_NONE,
 ];
 
+/// Options used to create the window surface
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackendConfig {
+    /// Samples per pixel of the window surface, resolved by the driver on swap.
+    /// 0 disables MSAA, the samples obtained are reported in `Limits::msaa_samples`
+    pub msaa_samples: u32,
+}
+
+#[cfg(target_os = "linux")]
+fn config_attribs(config: &BackendConfig) -> Vec<i32> {
+    let mut attribs = CONFIG_ATTRIBS[..CONFIG_ATTRIBS.len() - 1].to_vec();
+    if config.msaa_samples > 0 {
+        attribs.extend([
+            egl::EGL_SAMPLE_BUFFERS,
+            1,
+            egl::EGL_SAMPLES,
+            config.msaa_samples as _,
+        ]);
+    }
+    attribs.push(egl::EGL_NONE);
+    attribs
+}
+
 #[cfg(target_os = "linux")]
 static CONTEXT_ATTRIBS: &[i32] = &[egl::EGL_CONTEXT_CLIENT_VERSION, 3, egl::EGL_NONE];
 
@@ -60,6 +102,23 @@ type Context = EGLContext;
 #[cfg(target_os = "windows")]
 type Context = raw_gl_context::GlContext;
 
+/// Raw handle passed to `raw_gl_context`, which takes a window implementing the trait
+#[cfg(target_os = "windows")]
+struct WindowHandle(RawWindowHandle);
+
+#[cfg(target_os = "windows")]
+unsafe impl HasRawWindowHandle for WindowHandle {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.0
+    }
+}
+
+/// (texture, pipeline, location, sampler) of a texture slot
+type BoundTexture = (u64, u64, u32, Option<u64>);
+
+/// `glDrawElementsBaseVertex`, loaded from GLES 3.2 or `OES_draw_elements_base_vertex`
+type DrawElementsBaseVertexFn = unsafe extern "system" fn(u32, i32, u32, *const c_void, i32);
+
 pub struct GlesBackend {
     #[cfg(target_os = "linux")]
     display: EGLDisplay,
@@ -75,108 +134,367 @@ pub struct GlesBackend {
     texture_count: u64,
     pipeline_count: u64,
     render_target_count: u64,
+    query_count: u64,
     size: (i32, i32),
     dpi: f32,
     pipelines: HashMap<u64, InnerPipeline>,
+    compute_pipelines: HashMap<u64, InnerComputePipeline>,
     buffers: HashMap<u64, InnerBuffer>,
     textures: HashMap<u64, InnerTexture>,
+    sampler_count: u64,
+    samplers: HashMap<u64, u32>,
     render_targets: HashMap<u64, InnerRenderTexture>,
-    using_indices: bool,
+    queries: HashMap<u64, u32>,
+    fence_count: u64,
+    fences: HashMap<u64, GLsync>,
+    /// Vertex buffer used by each (pipeline, buffer slot) in the pipeline's VAO
+    vertex_streams: HashMap<(u64, u32), u64>,
+    /// Color textures of the render targets written since the last barrier
+    written_textures: HashSet<u64>,
+    /// Uniform and storage buffers bound to each (target, slot)
+    bound_blocks: HashMap<(u32, u32), u64>,
+    /// Texture bound to each slot during the pass
+    bound_textures: [Option<BoundTexture>; MAX_TEXTURE_SLOTS as usize],
+    /// Format of the index buffer bound in the pass, `None` draws the arrays
+    index_format: Option<IndexFormat>,
     current_pipeline: u64,
     limits: Limits,
+    info: BackendInfo,
     current_uniforms: Vec<u32>,
+    current_stencil: Option<StencilOptions>,
+    capturing_feedback: bool,
+    reset_gl_state_on_end: bool,
+    shader_storage_block_binding: Option<ShaderStorageBlockBindingFn>,
+    pipeline_fns: PipelineFns,
+    draw_elements_base_vertex: Option<DrawElementsBaseVertexFn>,
+    current_stats: FrameStats,
+    letterbox: Option<Rect>,
+    /// Size and dpi of the target of the current pass, the window or a render texture
+    target_size: (i32, i32),
+    target_dpi: f32,
+    renderable_formats: HashMap<TextureFormat, bool>,
+    compressed_formats: Vec<u32>,
+    last_stats: FrameStats,
 }
 
 impl GlesBackend {
+    /// Backend with the default config, `main` uses `with_config`
+    #[allow(dead_code)]
     pub fn new(window: &Window) -> Result<Self, String> {
-        #[cfg(target_os = "linux")]
-        let (display, context, surface) = {
-            let display =
-                egl::get_display(egl::EGL_DEFAULT_DISPLAY).ok_or("Faild to get egl display")?;
+        Self::with_config(window, &BackendConfig::default())
+    }
 
-            let mut major = 0;
-            let mut minor = 0;
+    pub fn with_config(window: &Window, config: &BackendConfig) -> Result<Self, String> {
+        Self::from_handle(window.raw_window_handle(), config)
+    }
 
-            egl::initialize(display, &mut major, &mut minor)
-                .then(|| ())
-                .ok_or("Failed to initialize egl")?;
+    /// Creates the backend from the raw handle of a window, X11 on linux and Win32 on windows.
+    /// Windows of other winit versions convert their handle to this `raw-window-handle` version
+    pub fn from_handle(handle: RawWindowHandle, config: &BackendConfig) -> Result<Self, String> {
+        match handle {
+            #[cfg(target_os = "linux")]
+            RawWindowHandle::Xlib(xlib) => Self::from_xlib_window(xlib.window, config),
 
-            egl::bind_api(egl::EGL_OPENGL_ES_API)
-                .then(|| ())
-                .ok_or("Failed to bind api")?;
+            #[cfg(target_os = "windows")]
+            RawWindowHandle::Win32(_) => {
+                let gl_config = raw_gl_context::GlConfig {
+                    samples: (config.msaa_samples > 0).then(|| config.msaa_samples as u8),
+                    ..Default::default()
+                };
+                let context =
+                    raw_gl_context::GlContext::create(&WindowHandle(handle), gl_config).unwrap();
 
-            let config =
-                egl::choose_config(display, CONFIG_ATTRIBS, 1).ok_or("Failed to choose config")?;
+                context.make_current();
 
-            let context =
-                egl::create_context(display, config, egl::EGL_NO_CONTEXT, CONTEXT_ATTRIBS)
-                    .ok_or("Failed to create context")?;
+                gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
 
-            let window = window.xlib_window().ok_or("Failed to get window")?;
+                Ok(Self::from_context(context))
+            }
 
-            let surface = egl::create_window_surface(display, config, window as _, &[])
-                .ok_or("Failed to create surface")?;
+            _ => Err(format!("Unsupported window handle: {:?}", handle)),
+        }
+    }
 
-            egl::make_current(display, surface, surface, context)
-                .then(|| ())
-                .ok_or("Failed to make the context current")?;
+    /// Creates the backend from the id of a X11 window
+    #[cfg(target_os = "linux")]
+    fn from_xlib_window(
+        window: std::os::raw::c_ulong,
+        config: &BackendConfig,
+    ) -> Result<Self, String> {
+        let display =
+            egl::get_display(egl::EGL_DEFAULT_DISPLAY).ok_or("Faild to get egl display")?;
 
-            gl::load_with(|s| egl::get_proc_address(s) as _);
+        let mut major = 0;
+        let mut minor = 0;
 
-            (display, context, surface)
-        };
+        egl::initialize(display, &mut major, &mut minor)
+            .then(|| ())
+            .ok_or("Failed to initialize egl")?;
 
-        #[cfg(target_os = "windows")]
-        let context = {
-            let context =
-                raw_gl_context::GlContext::create(&window, raw_gl_context::GlConfig::default())
-                    .unwrap();
+        egl::bind_api(egl::EGL_OPENGL_ES_API)
+            .then(|| ())
+            .ok_or("Failed to bind api")?;
 
-            context.make_current();
+        // fallback to a config without MSAA if the samples requested are not supported
+        let config = egl::choose_config(display, &config_attribs(config), 1)
+            .or_else(|| egl::choose_config(display, CONFIG_ATTRIBS, 1))
+            .ok_or("Failed to choose config")?;
 
-            gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+        let context = egl::create_context(display, config, egl::EGL_NO_CONTEXT, CONTEXT_ATTRIBS)
+            .ok_or("Failed to create context")?;
 
-            context
-        };
+        let surface = egl::create_window_surface(display, config, window as _, &[])
+            .ok_or("Failed to create surface")?;
 
+        egl::make_current(display, surface, surface, context)
+            .then(|| ())
+            .ok_or("Failed to make the context current")?;
+
+        gl::load_with(|s| egl::get_proc_address(s) as _);
+
+        Ok(Self::from_context(display, context, surface))
+    }
+
+    fn from_context(
+        #[cfg(target_os = "linux")] display: EGLDisplay,
+        context: Context,
+        #[cfg(target_os = "linux")] surface: EGLSurface,
+    ) -> Self {
         let mut limits = Limits::default();
         unsafe {
             gl::GetIntegerv(
                 gl::MAX_TEXTURE_SIZE,
                 &mut limits.max_texture_size as *mut _ as *mut GLint,
             );
+            gl::GetIntegerv(
+                gl::MAX_RENDERBUFFER_SIZE,
+                &mut limits.max_renderbuffer_size as *mut _ as *mut GLint,
+            );
+
+            let mut dims = [0; 2];
+            gl::GetIntegerv(gl::MAX_VIEWPORT_DIMS, dims.as_mut_ptr());
+            limits.max_viewport_dims = (dims[0] as _, dims[1] as _);
+
             gl::GetIntegerv(
                 gl::MAX_UNIFORM_BLOCK_SIZE,
                 &mut limits.max_uniform_blocks as *mut _ as *mut GLint,
             );
+            gl::GetIntegerv(
+                gl::MAX_TEXTURE_IMAGE_UNITS,
+                &mut limits.max_texture_image_units as *mut _ as *mut GLint,
+            );
+            gl::GetIntegerv(
+                gl::MAX_ARRAY_TEXTURE_LAYERS,
+                &mut limits.max_array_texture_layers as *mut _ as *mut GLint,
+            );
+            gl::GetIntegerv(
+                gl::SAMPLES,
+                &mut limits.msaa_samples as *mut _ as *mut GLint,
+            );
+            gl::GetIntegerv(
+                gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT,
+                &mut limits.uniform_buffer_offset_alignment as *mut _ as *mut GLint,
+            );
+
+            let mut range = [0.0f32; 2];
+            gl::GetFloatv(gl::ALIASED_POINT_SIZE_RANGE, range.as_mut_ptr());
+            limits.point_size_range = (range[0], range[1]);
+            gl::GetFloatv(gl::ALIASED_LINE_WIDTH_RANGE, range.as_mut_ptr());
+            limits.line_width_range = (range[0], range[1]);
+        }
+        limits.occlusion_query = gl::BeginQuery::is_loaded();
+        limits.image_load_store = gl::BindImageTexture::is_loaded();
+        if limits.image_load_store {
+            unsafe {
+                gl::GetIntegerv(
+                    gl::MAX_IMAGE_UNITS,
+                    &mut limits.max_image_units as *mut _ as *mut GLint,
+                );
+            }
         }
+        limits.parallel_shader_compile = has_extension("GL_KHR_parallel_shader_compile");
 
-        Ok(Self {
+        let info = BackendInfo {
+            version: gl_string(gl::VERSION),
+            renderer: gl_string(gl::RENDERER),
+            vendor: gl_string(gl::VENDOR),
+            glsl_version: gl_string(gl::SHADING_LANGUAGE_VERSION),
+        };
+
+        let mut backend = Self {
             #[cfg(target_os = "linux")]
             display,
-            #[cfg(target_os = "linux")]
             context,
             #[cfg(target_os = "linux")]
             surface,
 
-            #[cfg(target_os = "windows")]
-            context,
-
             pipeline_count: 0,
             buffer_count: 0,
             texture_count: 0,
             render_target_count: 0,
+            query_count: 0,
             size: (0, 0),
             dpi: 1.0,
             pipelines: HashMap::new(),
+            compute_pipelines: HashMap::new(),
             buffers: HashMap::new(),
             textures: HashMap::new(),
+            sampler_count: 0,
+            samplers: HashMap::new(),
             render_targets: HashMap::new(),
-            using_indices: false,
+            queries: HashMap::new(),
+            fence_count: 0,
+            fences: HashMap::new(),
+            vertex_streams: HashMap::new(),
+            written_textures: HashSet::new(),
+            bound_blocks: HashMap::new(),
+            bound_textures: Default::default(),
+            index_format: None,
             current_pipeline: 0,
             limits,
+            info,
             current_uniforms: vec![],
-        })
+            current_stencil: None,
+            capturing_feedback: false,
+            reset_gl_state_on_end: false,
+            shader_storage_block_binding: None,
+            pipeline_fns: PipelineFns::default(),
+            draw_elements_base_vertex: None,
+            current_stats: FrameStats::default(),
+            letterbox: None,
+            target_size: (0, 0),
+            target_dpi: 1.0,
+            renderable_formats: HashMap::new(),
+            compressed_formats: compressed_formats(),
+            last_stats: FrameStats::default(),
+        };
+
+        let block_binding = backend.get_proc_address("glShaderStorageBlockBinding");
+        if !block_binding.is_null() {
+            backend.shader_storage_block_binding = Some(unsafe {
+                std::mem::transmute::<*const c_void, ShaderStorageBlockBindingFn>(block_binding)
+            });
+        }
+
+        backend.pipeline_fns = PipelineFns {
+            indexed_blend: backend.load_indexed_blend(),
+            min_sample_shading: backend.load_min_sample_shading(),
+        };
+        backend.limits.sample_shading = backend.pipeline_fns.min_sample_shading.is_some();
+        backend.draw_elements_base_vertex = backend.load_draw_elements_base_vertex();
+        backend.limits.base_vertex = backend.draw_elements_base_vertex.is_some();
+
+        if backend.limits.parallel_shader_compile {
+            let max_threads = backend.get_proc_address("glMaxShaderCompilerThreadsKHR");
+            if !max_threads.is_null() {
+                unsafe {
+                    let max_threads = std::mem::transmute::<
+                        *const c_void,
+                        unsafe extern "system" fn(u32),
+                    >(max_threads);
+                    // let the driver choose the number of threads
+                    max_threads(u32::MAX);
+                }
+            }
+        }
+
+        backend
+    }
+}
+
+impl GlesBackend {
+    fn add_pipeline(&mut self, pipeline: InnerPipeline) -> u64 {
+        self.pipeline_count += 1;
+        self.pipelines.insert(self.pipeline_count, pipeline);
+        self.pipeline_count
+    }
+
+    fn validate_pipeline_options(&self, options: &PipelineOptions) -> Result<(), String> {
+        let indexed_blends = options
+            .attachment_blends
+            .as_ref()
+            .is_some_and(|blends| blends.len() > 1);
+        if indexed_blends && self.pipeline_fns.indexed_blend.is_none() {
+            return Err(
+                "Blending per attachment needs GLES 3.2 or OES_draw_buffers_indexed".to_string(),
+            );
+        }
+
+        if let Some(value) = options.min_sample_shading {
+            if self.pipeline_fns.min_sample_shading.is_none() {
+                return Err("Sample shading needs GLES 3.2 or OES_sample_shading".to_string());
+            }
+
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!(
+                    "Min sample shading {} must be between 0.0 and 1.0",
+                    value
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_draw_elements_base_vertex(&self) -> Option<DrawElementsBaseVertexFn> {
+        let f = ["glDrawElementsBaseVertex", "glDrawElementsBaseVertexOES"]
+            .iter()
+            .map(|name| self.get_proc_address(name))
+            .find(|f| !f.is_null())?;
+
+        Some(unsafe { std::mem::transmute::<*const c_void, DrawElementsBaseVertexFn>(f) })
+    }
+
+    fn load_min_sample_shading(&self) -> Option<MinSampleShadingFn> {
+        let f = ["glMinSampleShading", "glMinSampleShadingOES"]
+            .iter()
+            .map(|name| self.get_proc_address(name))
+            .find(|f| !f.is_null())?;
+
+        Some(unsafe { std::mem::transmute::<*const c_void, MinSampleShadingFn>(f) })
+    }
+
+    fn load_indexed_blend(&self) -> Option<IndexedBlendFns> {
+        let load = |name: &str| {
+            let f = self.get_proc_address(name);
+            if f.is_null() {
+                self.get_proc_address(&format!("{}OES", name))
+            } else {
+                f
+            }
+        };
+
+        let blend_func = load("glBlendFunci");
+        let blend_equation = load("glBlendEquationi");
+        if blend_func.is_null() || blend_equation.is_null() {
+            return None;
+        }
+
+        unsafe {
+            Some(IndexedBlendFns {
+                blend_func: std::mem::transmute::<
+                    *const c_void,
+                    unsafe extern "system" fn(u32, u32, u32),
+                >(blend_func),
+                blend_equation: std::mem::transmute::<
+                    *const c_void,
+                    unsafe extern "system" fn(u32, u32),
+                >(blend_equation),
+            })
+        }
+    }
+
+    /// Returns the address of a gl function, null if it cannot be found.
+    ///
+    /// This is meant to load extension entry points that the backend doesn't wrap.
+    /// Calling the returned function is unsafe, and changing the gl state with it
+    /// can corrupt the state the backend keeps cached (bound pipeline, buffers, etc...)
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        #[cfg(target_os = "linux")]
+        return egl::get_proc_address(name) as *const c_void;
+
+        #[cfg(target_os = "windows")]
+        return self.context.get_proc_address(name) as *const c_void;
     }
 }
 
@@ -191,17 +509,27 @@ impl Drop for GlesBackend {
 
 impl GlesBackend {
     #[inline(always)]
-    fn clear(&self, color: &Option<Color>, depth: &Option<f32>, stencil: &Option<i32>) {
+    fn clear(
+        &self,
+        color: &Option<Color>,
+        colors: &[Color],
+        depth: &Option<f32>,
+        stencil: &Option<i32>,
+    ) {
         clear(&self.context, color, depth, stencil);
+        clear_attachments(&self.context, colors);
     }
 
     fn begin(
-        &self,
+        &mut self,
         target: Option<u64>,
         color: &Option<Color>,
+        colors: &[Color],
         depth: &Option<f32>,
         stencil: &Option<i32>,
     ) {
+        self.bound_textures = Default::default();
+
         let render_target = match target {
             Some(id) => self.render_targets.get(&id),
             _ => None,
@@ -220,9 +548,18 @@ impl GlesBackend {
             }
         };
 
+        self.target_size = (width, height);
+        self.target_dpi = dpi;
+
         self.viewport(0.0, 0.0, width as _, height as _, dpi);
 
-        self.clear(color, depth, stencil);
+        self.clear(color, colors, depth, stencil);
+
+        // the bars are cleared with the rest of the screen, then kept out of the pass
+        if let (None, Some(rect)) = (render_target, self.letterbox) {
+            self.viewport(rect.x, rect.y, rect.width, rect.height, dpi);
+            self.scissors(rect.x, rect.y, rect.width, rect.height, dpi);
+        }
     }
 
     #[inline]
@@ -237,20 +574,21 @@ impl GlesBackend {
 
     #[inline]
     fn scissors(&self, x: f32, y: f32, width: f32, height: f32, dpi: f32) {
-        let canvas_height = ((self.size.1 - (height + y) as i32) as f32 * dpi) as i32;
-        let x = x * dpi;
-        let width = width * dpi;
-        let height = height * dpi;
+        let (x, y, width, height) = scissor_rect(self.target_size.1, dpi, x, y, width, height);
 
         unsafe {
             gl::Enable(gl::SCISSOR_TEST);
-            gl::Scissor(x as _, canvas_height, width as _, height as _);
+            gl::Scissor(x, y, width, height);
         }
     }
 
     fn end(&mut self) {
+        // a capture left open would make the next pass draws fail
+        self.end_transform_feedback();
+
         unsafe {
             gl::Disable(gl::SCISSOR_TEST);
+            gl::Disable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
             gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
@@ -258,48 +596,311 @@ impl GlesBackend {
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
         }
 
-        self.using_indices = false;
+        if self.reset_gl_state_on_end {
+            self.reset_gl_state();
+        }
+
+        self.index_format = None;
+        self.bound_textures = Default::default();
+    }
+
+    #[inline]
+    fn primitive_restart(&self, enabled: bool) {
+        unsafe {
+            if enabled {
+                gl::Enable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+            } else {
+                gl::Disable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+            }
+        }
     }
 
     fn clean_pipeline(&mut self, id: u64) {
         if let Some(pip) = self.pipelines.remove(&id) {
             pip.clean(&self.context);
+        } else if let Some(pip) = self.compute_pipelines.remove(&id) {
+            pip.clean(&self.context);
+        } else {
+            return;
         }
+
+        self.buffers
+            .values_mut()
+            .for_each(|buffer| buffer.remove_pipeline(id));
+        self.vertex_streams
+            .retain(|(pipeline, _), _| *pipeline != id);
+    }
+
+    /// Program of the current graphics or compute pipeline
+    fn current_program(&self) -> Option<u32> {
+        self.pipelines
+            .get(&self.current_pipeline)
+            .map(|pip| pip.program)
+            .or_else(|| {
+                self.compute_pipelines
+                    .get(&self.current_pipeline)
+                    .map(|pip| pip.program)
+            })
     }
 
     fn set_pipeline(&mut self, id: u64, options: &PipelineOptions) {
         if let Some(pip) = self.pipelines.get(&id) {
-            pip.bind(&self.context, options);
-            self.using_indices = false;
+            pip.bind(&self.context, options, &self.pipeline_fns);
+            self.index_format = None;
+            self.current_pipeline = id;
+            self.current_uniforms = pip.uniform_locations.clone();
+            self.current_stencil = options.stencil;
+            self.rebind_blocks();
+        }
+    }
+
+    fn set_compute_pipeline(&mut self, id: u64) {
+        if let Some(pip) = self.compute_pipelines.get(&id) {
+            pip.bind(&self.context);
             self.current_pipeline = id;
             self.current_uniforms = pip.uniform_locations.clone();
+            self.rebind_blocks();
+        }
+    }
+
+    fn dispatch(&mut self, x: u32, y: u32, z: u32) {
+        match self.compute_pipelines.get(&self.current_pipeline) {
+            Some(pip) => pip.dispatch(&self.context, x, y, z),
+            None => eprintln!("Dispatch called without a compute pipeline set"),
+        }
+    }
+
+    fn set_depth_compare(&mut self, compare: &CompareMode) {
+        unsafe {
+            match compare.to_gl() {
+                Some(func) => {
+                    gl::Enable(gl::DEPTH_TEST);
+                    gl::DepthFunc(func);
+                }
+                None => gl::Disable(gl::DEPTH_TEST),
+            }
+        }
+    }
+
+    fn set_depth_write(&mut self, enabled: bool) {
+        unsafe {
+            gl::DepthMask(enabled as _);
+        }
+    }
+
+    fn set_draw_buffers(&mut self, target: Option<u64>, buffers: &[Option<u32>]) {
+        // the window only has the back buffer
+        let attachments = target
+            .and_then(|id| self.render_targets.get(&id))
+            .map_or(1, |rt| rt.color_attachments);
+        if buffers.len() > attachments {
+            eprintln!(
+                "Draw buffers count '{}' exceeds the color attachments ({})",
+                buffers.len(),
+                attachments
+            );
+            return;
+        }
+
+        // the indices are checked by `validate_limits`
+        let gl_buffers = buffers
+            .iter()
+            .map(|buffer| match buffer {
+                Some(n) if target.is_some() => gl::COLOR_ATTACHMENT0 + n,
+                Some(_) => gl::BACK,
+                None => gl::NONE,
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            gl::DrawBuffers(gl_buffers.len() as _, gl_buffers.as_ptr());
+        }
+    }
+
+    fn begin_transform_feedback(&mut self, primitive: &DrawPrimitive, buffer: u64) {
+        // the draws must use the same primitive, GLES doesn't capture strips
+        let mode = match primitive {
+            DrawPrimitive::Points => gl::POINTS,
+            DrawPrimitive::Lines => gl::LINES,
+            DrawPrimitive::Triangles => gl::TRIANGLES,
+            DrawPrimitive::LineStrip | DrawPrimitive::TriangleStrip => {
+                eprintln!("Transform feedback can't capture {:?}", primitive);
+                return;
+            }
+        };
+
+        if self.capturing_feedback {
+            eprintln!("Transform feedback already started");
+            return;
+        }
+
+        match self.buffers.get(&buffer) {
+            Some(buff) => {
+                buff.bind_feedback(&self.context);
+                unsafe {
+                    gl::BeginTransformFeedback(mode);
+                }
+                self.capturing_feedback = true;
+            }
+            None => eprintln!("Invalid transform feedback buffer '{}'", buffer),
+        }
+    }
+
+    /// GLES 3 only captures the array draws, the indexed ones are errors during a capture
+    fn indexed_draw_in_feedback(&self) -> bool {
+        if self.capturing_feedback && self.index_format.is_some() {
+            eprintln!("Indexed draws can't be captured by the transform feedback");
+            return true;
+        }
+
+        false
+    }
+
+    fn end_transform_feedback(&mut self) {
+        if !self.capturing_feedback {
+            return;
+        }
+
+        unsafe {
+            gl::EndTransformFeedback();
+            gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, 0, 0);
+        }
+        self.capturing_feedback = false;
+    }
+
+    fn set_stencil_reference(&mut self, reference: u32) {
+        let stencil = self.current_stencil.unwrap_or_default();
+        unsafe {
+            gl::StencilFunc(
+                stencil.compare.to_gl().unwrap_or(gl::ALWAYS),
+                reference as _,
+                stencil.read_mask,
+            );
         }
     }
 
     fn bind_buffer(&mut self, id: u64) {
-        if let Some(buffer) = self.buffers.get_mut(&id) {
+        if let Some(buffer) = self.buffers.get(&id) {
+            let mut enable_attrs = false;
+            let mut block = None;
             match &buffer.kind {
-                Kind::Index => {
-                    self.using_indices = true;
+                Kind::Vertex(attrs) => {
+                    // the VAO keeps the attribute pointers, only update them when
+                    // another buffer was used for this slot
+                    let stream = (self.current_pipeline, attrs.slot);
+                    enable_attrs = self.vertex_streams.insert(stream, id) != Some(id);
                 }
-                Kind::Uniform(_slot, _name) => {
-                    if !buffer.block_binded {
-                        buffer.bind_ubo_block(
-                            &self.context,
-                            self.pipelines.get(&self.current_pipeline).as_ref().unwrap(),
-                        );
-                    }
+                Kind::Index(format) => {
+                    self.index_format = Some(*format);
                 }
-                _ => {}
+                Kind::Uniform(slot, _) => block = Some((gl::UNIFORM_BUFFER, *slot)),
+                Kind::Storage(slot, _) => block = Some((gl::SHADER_STORAGE_BUFFER, *slot)),
+            }
+
+            buffer.bind(&self.context);
+
+            if enable_attrs {
+                buffer.enable_attrs(&self.context);
+            }
+
+            if let Some(block) = block {
+                self.bound_blocks.insert(block, id);
+                self.bind_block(id);
             }
+        }
+    }
+
+    fn bind_uniform_range(&mut self, id: u64, slot: u32, offset: u32, size: u32) {
+        // the alignment is checked by `validate_limits`, the size needs the buffer
+        if let Some(buffer) = self.buffers.get(&id) {
+            if let Err(e) = buffer.bind_range(&self.context, slot, offset, size) {
+                eprintln!("{}", e);
+                return;
+            }
+
+            self.bound_blocks.insert((gl::UNIFORM_BUFFER, slot), id);
+            self.bind_block(id);
+        }
+    }
+
+    /// Resolve the uniform or storage block of the buffer in the current pipeline
+    fn bind_block(&mut self, id: u64) {
+        let program = match self.current_program() {
+            Some(program) => program,
+            None => return,
+        };
 
-            buffer.bind(&self.context, Some(self.current_pipeline));
+        let pipeline_id = self.current_pipeline;
+        let block_binding = self.shader_storage_block_binding;
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            match &buffer.kind {
+                Kind::Uniform(..) => buffer.bind_ubo_block(&self.context, pipeline_id, program),
+                Kind::Storage(..) => {
+                    buffer.bind_ssbo_block(&self.context, pipeline_id, program, block_binding)
+                }
+                _ => {}
+            }
         }
     }
 
-    fn bind_texture(&mut self, id: u64, slot: u32, location: u32) {
+    /// The buffer bases stay bound when the pipeline changes,
+    /// so the blocks of the new pipeline must point to them too
+    fn rebind_blocks(&mut self) {
+        let ids: Vec<u64> = self.bound_blocks.values().copied().collect();
+        ids.into_iter().for_each(|id| self.bind_block(id));
+    }
+
+    fn bind_texture(&mut self, id: u64, slot: u32, location: u32, sampler: Option<u64>) {
+        let max_slots = self.limits.max_texture_image_units.min(MAX_TEXTURE_SLOTS);
+        if slot >= max_slots {
+            eprintln!(
+                "Texture slot '{}' exceeds the max texture slots ({})",
+                slot, max_slots
+            );
+            return;
+        }
+
+        if self.written_textures.contains(&id) {
+            self.barrier();
+        }
+
+        // the same texture and sampler uniform are still set for this slot
+        let bound = Some((id, self.current_pipeline, location, sampler));
+        if self.bound_textures[slot as usize] == bound {
+            return;
+        }
+
         if let Some(texture) = self.textures.get(&id) {
             texture.bind(&self.context, slot, self.get_uniform_loc(&location));
+
+            // 0 removes the sampler of a previous bind, using the texture filters again
+            let gl_sampler = sampler
+                .and_then(|id| self.samplers.get(&id))
+                .copied()
+                .unwrap_or(0);
+            unsafe {
+                gl::BindSampler(slot, gl_sampler);
+            }
+
+            self.bound_textures[slot as usize] = bound;
+        }
+    }
+
+    fn set_uniform(&mut self, location: u32, value: &UniformValue) {
+        let loc = match self.current_uniforms.get(location as usize) {
+            Some(loc) => *loc as i32,
+            None => {
+                eprintln!("Invalid uniform location '{}'", location);
+                return;
+            }
+        };
+
+        unsafe {
+            match value {
+                UniformValue::F32(v) => gl::Uniform1f(loc, *v),
+                UniformValue::Vec4(v) => gl::Uniform4fv(loc, 1, v.as_ptr()),
+                UniformValue::Mat4(v) => gl::UniformMatrix4fv(loc, 1, gl::FALSE, v.as_ptr()),
+            }
         }
     }
 
@@ -309,17 +910,27 @@ impl GlesBackend {
     }
 
     fn clean_buffer(&mut self, id: u64) {
+        self.bound_blocks.retain(|_, buffer| *buffer != id);
         if let Some(buffer) = self.buffers.remove(&id) {
             buffer.clean(&self.context);
         }
     }
 
     fn clean_texture(&mut self, id: u64) {
+        self.written_textures.remove(&id);
         if let Some(texture) = self.textures.remove(&id) {
             texture.clean(&self.context);
         }
     }
 
+    fn clean_sampler(&mut self, id: u64) {
+        if let Some(sampler) = self.samplers.remove(&id) {
+            unsafe {
+                gl::DeleteSamplers(1, &sampler as *const _);
+            }
+        }
+    }
+
     fn clean_render_target(&mut self, id: u64) {
         if let Some(rt) = self.render_targets.remove(&id) {
             rt.clean(&self.context);
@@ -327,39 +938,211 @@ impl GlesBackend {
     }
 
     fn draw(&mut self, primitive: &DrawPrimitive, offset: i32, count: i32) {
+        if self.indexed_draw_in_feedback() {
+            return;
+        }
+
+        self.current_stats.add_draw(primitive, count);
         unsafe {
-            if self.using_indices {
+            if let Some(format) = self.index_format {
                 gl::DrawElements(
                     primitive.to_gl(),
                     count,
-                    gl::UNSIGNED_INT,
-                    (offset * 4) as *const _,
+                    format.to_gl(),
+                    index_byte_offset(format, offset) as *const _,
                 );
             } else {
                 gl::DrawArrays(primitive.to_gl(), offset, count);
             }
         }
     }
-    fn draw_instanced(&mut self, primitive: &DrawPrimitive, offset: i32, count: i32, length: i32) {
-        unsafe {
-            if self.using_indices {
-                gl::DrawElementsInstanced(
-                    primitive.to_gl(),
-                    count,
-                    gl::UNSIGNED_INT,
-                    offset as *const _,
-                    length,
+
+    fn bind_image_texture(
+        &mut self,
+        id: u64,
+        unit: u32,
+        access: &ImageAccess,
+        format: &TextureFormat,
+    ) {
+        // the support, unit and format are checked by `validate_limits`
+        let image_format = match image_format(format) {
+            Some(image_format) => image_format,
+            None => return,
+        };
+
+        if let Some(texture) = self.textures.get(&id) {
+            if !texture.immutable {
+                eprintln!("Textures bound as images must be created with `with_image_storage`");
+                return;
+            }
+
+            unsafe {
+                gl::BindImageTexture(
+                    unit,
+                    texture.texture,
+                    0,
+                    (texture.target != gl::TEXTURE_2D) as _,
+                    0,
+                    access.to_gl(),
+                    image_format,
                 );
-            } else {
-                gl::DrawArraysInstanced(primitive.to_gl(), offset, count, length);
             }
         }
     }
-}
 
-impl DeviceBackend for GlesBackend {
-    fn limits(&self) -> Limits {
-        self.limits
+    fn draw_base_vertex(
+        &mut self,
+        primitive: &DrawPrimitive,
+        offset: i32,
+        count: i32,
+        base_vertex: i32,
+    ) {
+        if self.indexed_draw_in_feedback() {
+            return;
+        }
+
+        let format = match self.index_format {
+            Some(format) => format,
+            None => {
+                eprintln!("DrawElementsBaseVertex needs an index buffer");
+                return;
+            }
+        };
+
+        let draw_elements_base_vertex = match self.draw_elements_base_vertex {
+            Some(f) => f,
+            None => {
+                eprintln!("DrawElementsBaseVertex needs GLES 3.2 or OES_draw_elements_base_vertex");
+                return;
+            }
+        };
+
+        self.current_stats.add_draw(primitive, count);
+        unsafe {
+            draw_elements_base_vertex(
+                primitive.to_gl(),
+                count,
+                format.to_gl(),
+                index_byte_offset(format, offset) as *const _,
+                base_vertex,
+            );
+        }
+    }
+
+    fn draw_range_elements(
+        &mut self,
+        primitive: &DrawPrimitive,
+        start: u32,
+        end: u32,
+        offset: i32,
+        count: i32,
+    ) {
+        if self.indexed_draw_in_feedback() {
+            return;
+        }
+
+        let format = match self.index_format {
+            Some(format) => format,
+            None => {
+                eprintln!("DrawRangeElements needs an index buffer, drawing the arrays instead");
+                self.draw(primitive, offset, count);
+                return;
+            }
+        };
+
+        self.current_stats.add_draw(primitive, count);
+        unsafe {
+            gl::DrawRangeElements(
+                primitive.to_gl(),
+                start,
+                end,
+                count,
+                format.to_gl(),
+                index_byte_offset(format, offset) as *const _,
+            );
+        }
+    }
+
+    fn draw_instanced(&mut self, primitive: &DrawPrimitive, offset: i32, count: i32, length: i32) {
+        if self.indexed_draw_in_feedback() {
+            return;
+        }
+
+        self.current_stats
+            .add_instanced_draw(primitive, count, length);
+        unsafe {
+            if let Some(format) = self.index_format {
+                gl::DrawElementsInstanced(
+                    primitive.to_gl(),
+                    count,
+                    format.to_gl(),
+                    index_byte_offset(format, offset) as *const _,
+                    length,
+                );
+            } else {
+                gl::DrawArraysInstanced(primitive.to_gl(), offset, count, length);
+            }
+        }
+    }
+}
+
+/// Byte offset in the bound index buffer of the first index drawn
+fn index_byte_offset(format: IndexFormat, offset: i32) -> usize {
+    offset.max(0) as usize * format.size()
+}
+
+/// Scissor box in the pixels of the target, from a top-left origin rect in the target units
+fn scissor_rect(
+    target_height: i32,
+    dpi: f32,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> (i32, i32, i32, i32) {
+    let bottom = ((target_height - (height + y) as i32) as f32 * dpi) as i32;
+    (
+        (x * dpi) as i32,
+        bottom,
+        (width * dpi) as i32,
+        (height * dpi) as i32,
+    )
+}
+
+/// Returns if the context lists the extension in `GL_EXTENSIONS`
+fn has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count.max(0) as u32).any(|i| {
+            let ptr = gl::GetStringi(gl::EXTENSIONS, i);
+            !ptr.is_null()
+                && std::ffi::CStr::from_ptr(ptr as *const _).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+/// String returned by `glGetString`, empty if the query fails
+fn gl_string(name: u32) -> String {
+    unsafe {
+        let ptr = gl::GetString(name);
+        if ptr.is_null() {
+            return String::new();
+        }
+
+        std::ffi::CStr::from_ptr(ptr as *const _)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl DeviceBackend for GlesBackend {
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn info(&self) -> BackendInfo {
+        self.info.clone()
     }
 
     fn create_pipeline(
@@ -367,73 +1150,215 @@ impl DeviceBackend for GlesBackend {
         vertex_source: &[u8],
         fragment_source: &[u8],
         vertex_attrs: &[VertexAttr],
+        feedback_varyings: &[String],
         options: PipelineOptions,
     ) -> Result<u64, String> {
+        self.validate_pipeline_options(&options)?;
+
         let vertex_source = std::str::from_utf8(vertex_source).map_err(|e| e.to_string())?;
         let fragment_source = std::str::from_utf8(fragment_source).map_err(|e| e.to_string())?;
 
-        let inner_pipeline =
-            InnerPipeline::new(&self.context, vertex_source, fragment_source, vertex_attrs)?;
-        inner_pipeline.bind(&self.context, &options);
+        // creating the VAO binds it, keep the one of the current pipeline so
+        // a pipeline created in the middle of a frame doesn't change the state
+        let mut current_vao = 0;
+        unsafe {
+            gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut current_vao);
+        }
 
-        self.pipeline_count += 1;
-        self.pipelines.insert(self.pipeline_count, inner_pipeline);
+        let inner_pipeline = InnerPipeline::new(
+            &self.context,
+            vertex_source,
+            fragment_source,
+            vertex_attrs,
+            feedback_varyings,
+        );
 
-        self.set_pipeline(self.pipeline_count, &options);
-        Ok(self.pipeline_count)
+        unsafe {
+            gl::BindVertexArray(current_vao as _);
+        }
+        let inner_pipeline = inner_pipeline?;
+
+        Ok(self.add_pipeline(inner_pipeline))
+    }
+
+    fn create_pipelines(&mut self, descs: &[PipelineDesc]) -> Vec<Result<u64, String>> {
+        let mut current_vao = 0;
+        unsafe {
+            gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut current_vao);
+        }
+
+        let mut results = Vec::with_capacity(descs.len());
+        let mut pending = vec![];
+        for (i, desc) in descs.iter().enumerate() {
+            let started = self.validate_pipeline_options(&desc.options).and_then(|_| {
+                InnerPipeline::start(&self.context, desc.vertex, desc.fragment, desc.attrs(), &[])
+            });
+
+            match started {
+                Ok(pipeline) => {
+                    pending.push((i, pipeline));
+                    results.push(Ok(0));
+                }
+                Err(err) => results.push(Err(err)),
+            }
+        }
+
+        // finish the pipelines as the driver links them, checking one that isn't
+        // ready would wait for it
+        let parallel = self.limits.parallel_shader_compile;
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|(_, pipeline)| pipeline.is_ready(parallel));
+
+            for (i, pipeline) in ready {
+                results[i] = pipeline
+                    .finish(&self.context)
+                    .map(|inner| self.add_pipeline(inner));
+            }
+
+            pending = waiting;
+            if !pending.is_empty() {
+                std::thread::yield_now();
+            }
+        }
+
+        unsafe {
+            gl::BindVertexArray(current_vao as _);
+        }
+
+        results
     }
 
     fn create_vertex_buffer(
         &mut self,
         attrs: &[VertexAttr],
         step_mode: VertexStepMode,
+        stride: u32,
     ) -> Result<u64, String> {
-        let (stride, inner_attrs) = get_inner_attrs(attrs);
-        let kind = Kind::Vertex(VertexAttributes::new(stride, inner_attrs, step_mode));
-        let mut inner_buffer = InnerBuffer::new(&self.context, kind, true)?;
-        inner_buffer.bind(&self.context, Some(self.current_pipeline));
+        let (_, inner_attrs) = get_inner_attrs(attrs);
+        let slot = attrs.first().map_or(0, |attr| attr.slot);
+        let kind = Kind::Vertex(VertexAttributes::new(
+            slot,
+            stride as _,
+            inner_attrs,
+            step_mode,
+        ));
+        let inner_buffer = InnerBuffer::new(&self.context, kind, true)?;
+        inner_buffer.bind(&self.context);
         self.buffer_count += 1;
         self.buffers.insert(self.buffer_count, inner_buffer);
         Ok(self.buffer_count)
     }
 
+    fn create_sampler(&mut self, info: &SamplerInfo) -> Result<u64, String> {
+        let sampler = unsafe { create_sampler(&self.context, info) };
+        self.sampler_count += 1;
+        self.samplers.insert(self.sampler_count, sampler);
+        Ok(self.sampler_count)
+    }
+
     fn create_index_buffer(&mut self) -> Result<u64, String> {
-        let mut inner_buffer = InnerBuffer::new(&self.context, Kind::Index, true)?;
-        inner_buffer.bind(&self.context, Some(self.current_pipeline));
+        let inner_buffer = InnerBuffer::new(&self.context, Kind::Index(IndexFormat::UInt32), true)?;
+        inner_buffer.bind(&self.context);
         self.buffer_count += 1;
         self.buffers.insert(self.buffer_count, inner_buffer);
         Ok(self.buffer_count)
     }
 
     fn create_uniform_buffer(&mut self, slot: u32, name: &str) -> Result<u64, String> {
-        let mut inner_buffer =
-            InnerBuffer::new(&self.context, Kind::Uniform(slot, name.to_string()), true)?;
-        inner_buffer.bind(&self.context, Some(self.current_pipeline));
+        let name = CString::new(name).map_err(|e| e.to_string())?;
+        let inner_buffer = InnerBuffer::new(&self.context, Kind::Uniform(slot, name), true)?;
+        inner_buffer.bind(&self.context);
         self.buffer_count += 1;
         self.buffers.insert(self.buffer_count, inner_buffer);
         Ok(self.buffer_count)
     }
 
+    fn create_storage_buffer(&mut self, slot: u32, name: &str) -> Result<u64, String> {
+        let name = CString::new(name).map_err(|e| e.to_string())?;
+        let inner_buffer = InnerBuffer::new(&self.context, Kind::Storage(slot, name), true)?;
+        inner_buffer.bind(&self.context);
+        self.buffer_count += 1;
+        self.buffers.insert(self.buffer_count, inner_buffer);
+        Ok(self.buffer_count)
+    }
+
+    fn create_compute_pipeline(&mut self, source: &[u8]) -> Result<u64, String> {
+        let source = std::str::from_utf8(source).map_err(|e| e.to_string())?;
+
+        let inner_pipeline = InnerComputePipeline::new(&self.context, source)?;
+
+        // graphics and compute pipelines share the ids, they are dropped as the same resource
+        self.pipeline_count += 1;
+        self.compute_pipelines
+            .insert(self.pipeline_count, inner_pipeline);
+        Ok(self.pipeline_count)
+    }
+
     fn set_buffer_data(&mut self, id: u64, data: &[u8]) {
         if let Some(buffer) = self.buffers.get_mut(&id) {
-            buffer.bind(&self.context, None);
+            buffer.bind(&self.context);
             buffer.update(&self.context, data);
+            self.current_stats.buffer_uploads += 1;
+        }
+    }
+
+    fn set_index_format(&mut self, id: u64, format: IndexFormat) {
+        if let Some(InnerBuffer {
+            kind: Kind::Index(index_format),
+            ..
+        }) = self.buffers.get_mut(&id)
+        {
+            *index_format = format;
+        }
+    }
+
+    fn set_buffer_data_orphaned(&mut self, id: u64, data: &[u8]) {
+        if let Some(buffer) = self.buffers.get_mut(&id) {
+            buffer.bind(&self.context);
+            buffer.update_orphaned(&self.context, data);
+            self.current_stats.buffer_uploads += 1;
         }
     }
 
+    fn set_buffer_sub_data(&mut self, id: u64, offset: usize, data: &[u8]) -> Result<(), String> {
+        let buffer = self.buffers.get_mut(&id).ok_or(format!(
+            "Error updating the buffer: buffer id '{}' not found.",
+            id
+        ))?;
+
+        buffer.bind(&self.context);
+        buffer.update_range(&self.context, offset, data)?;
+        self.current_stats.buffer_uploads += 1;
+        Ok(())
+    }
+
     fn render(&mut self, commands: &[Commands], target: Option<u64>) {
+        if let Some(rt) = target.and_then(|id| self.render_targets.get(&id)) {
+            self.written_textures.insert(rt.texture_id);
+        }
+
         commands.iter().for_each(|cmd| {
             use Commands::*;
 
             match cmd {
                 Begin {
                     color,
+                    colors,
                     depth,
                     stencil,
-                } => self.begin(target, color, depth, stencil),
+                } => self.begin(target, color, colors, depth, stencil),
                 End => self.end(),
                 Pipeline { id, options } => self.set_pipeline(*id, options),
+                ComputePipeline { id } => self.set_compute_pipeline(*id),
                 BindBuffer { id } => self.bind_buffer(*id),
+                BindUniformRange {
+                    id,
+                    slot,
+                    offset,
+                    size,
+                } => self.bind_uniform_range(*id, *slot, *offset, *size),
                 Draw {
                     primitive,
                     offset,
@@ -445,30 +1370,91 @@ impl DeviceBackend for GlesBackend {
                     count,
                     length,
                 } => self.draw_instanced(primitive, *offset, *count, *length),
-                BindTexture { id, slot, location } => self.bind_texture(*id, *slot, *location),
+                DrawRangeElements {
+                    primitive,
+                    start,
+                    end,
+                    offset,
+                    count,
+                } => self.draw_range_elements(primitive, *start, *end, *offset, *count),
+                BindImageTexture {
+                    id,
+                    unit,
+                    access,
+                    format,
+                } => self.bind_image_texture(*id, *unit, access, format),
+                DrawBaseVertex {
+                    primitive,
+                    offset,
+                    count,
+                    base_vertex,
+                } => self.draw_base_vertex(primitive, *offset, *count, *base_vertex),
+                BindTexture {
+                    id,
+                    slot,
+                    location,
+                    sampler,
+                } => self.bind_texture(*id, *slot, *location, *sampler),
+                BindTextures { textures } => textures.iter().for_each(|(id, slot, location)| {
+                    self.bind_texture(*id, *slot, *location, None)
+                }),
                 Size { width, height } => self.set_size(*width, *height),
                 Viewport {
                     x,
                     y,
                     width,
                     height,
-                } => self.viewport(*x, *y, *width, *height, self.dpi),
+                } => self.viewport(*x, *y, *width, *height, self.target_dpi),
                 Scissors {
                     x,
                     y,
                     width,
                     height,
-                } => self.scissors(*x, *y, *width, *height, self.dpi),
+                } => self.scissors(*x, *y, *width, *height, self.target_dpi),
+                Dispatch { x, y, z } => self.dispatch(*x, *y, *z),
+                PrimitiveRestart { enabled } => self.primitive_restart(*enabled),
+                DepthCompare { compare } => self.set_depth_compare(compare),
+                DepthWrite { enabled } => self.set_depth_write(*enabled),
+                DrawBuffers { buffers } => self.set_draw_buffers(target, buffers),
+                StencilReference { reference } => self.set_stencil_reference(*reference),
+                BeginTransformFeedback { primitive, buffer } => {
+                    self.begin_transform_feedback(primitive, *buffer)
+                }
+                EndTransformFeedback => self.end_transform_feedback(),
+                Uniform { location, value } => self.set_uniform(*location, value),
             }
         });
     }
 
+    fn texture_ids(&self) -> Vec<u64> {
+        let attachments: HashSet<u64> = self
+            .render_targets
+            .values()
+            .flat_map(|rt| std::iter::once(rt.texture_id).chain(rt.depth_texture_id))
+            .collect();
+
+        self.textures
+            .keys()
+            .filter(|id| !attachments.contains(id))
+            .copied()
+            .collect()
+    }
+
+    fn pipeline_ids(&self) -> Vec<u64> {
+        self.pipelines
+            .keys()
+            .chain(self.compute_pipelines.keys())
+            .copied()
+            .collect()
+    }
+
     fn clean(&mut self, to_clean: &[ResourceId]) {
         to_clean.iter().for_each(|res| match &res {
             ResourceId::Pipeline(id) => self.clean_pipeline(*id),
             ResourceId::Buffer(id) => self.clean_buffer(*id),
             ResourceId::Texture(id) => self.clean_texture(*id),
             ResourceId::RenderTexture(id) => self.clean_render_target(*id),
+            ResourceId::Sampler(id) => self.clean_sampler(*id),
         })
     }
 
@@ -480,7 +1466,28 @@ impl DeviceBackend for GlesBackend {
         self.dpi = scale_factor as _;
     }
 
+    fn set_mipmap_hint(&mut self, hint: MipmapHint) {
+        unsafe {
+            gl::Hint(gl::GENERATE_MIPMAP_HINT, hint.to_gl());
+        }
+    }
+
+    fn set_letterbox(&mut self, rect: Option<Rect>) {
+        self.letterbox = rect;
+    }
+
     fn create_texture(&mut self, info: &TextureInfo) -> Result<u64, String> {
+        if info.format.is_compressed()
+            && !self
+                .compressed_formats
+                .contains(&texture_internal_format(&info.format))
+        {
+            return Err(format!(
+                "Compressed format {:?} is not supported by the gpu",
+                info.format
+            ));
+        }
+
         let inner_texture = InnerTexture::new(&self.context, info)?;
         self.texture_count += 1;
         self.textures.insert(self.texture_count, inner_texture);
@@ -490,6 +1497,7 @@ impl DeviceBackend for GlesBackend {
     fn create_render_texture(
         &mut self,
         texture_id: u64,
+        depth_texture_id: Option<u64>,
         info: &TextureInfo,
     ) -> Result<u64, String> {
         let texture = self.textures.get(&texture_id).ok_or(format!(
@@ -497,29 +1505,232 @@ impl DeviceBackend for GlesBackend {
             texture_id
         ))?;
 
-        let inner_rt = InnerRenderTexture::new(&self.context, texture, info)?;
+        let depth_texture = match depth_texture_id {
+            Some(id) => Some((
+                id,
+                self.textures.get(&id).ok_or(format!(
+                    "Error creating render target: depth texture id '{}' not found.",
+                    id
+                ))?,
+            )),
+            None => None,
+        };
+
+        let inner_rt =
+            InnerRenderTexture::new(&self.context, texture_id, texture, depth_texture, info)?;
         self.render_target_count += 1;
         self.render_targets
             .insert(self.render_target_count, inner_rt);
         Ok(self.render_target_count)
     }
 
+    fn resize_render_texture(
+        &mut self,
+        render_texture: u64,
+        texture_id: u64,
+        depth_texture_id: Option<u64>,
+        info: &TextureInfo,
+    ) -> Result<(), String> {
+        if !self.render_targets.contains_key(&render_texture) {
+            return Err(format!(
+                "Error resizing render target: render target id '{}' not found.",
+                render_texture
+            ));
+        }
+
+        let texture = InnerTexture::new(&self.context, info)?;
+        let depth_texture = match depth_texture_info(info) {
+            Some(depth_info) => match InnerTexture::new(&self.context, &depth_info) {
+                Ok(depth) => Some(depth),
+                Err(e) => {
+                    texture.clean(&self.context);
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
+        let inner_rt = match InnerRenderTexture::new(
+            &self.context,
+            texture_id,
+            &texture,
+            depth_texture_id.zip(depth_texture.as_ref()),
+            info,
+        ) {
+            Ok(rt) => rt,
+            Err(e) => {
+                texture.clean(&self.context);
+                if let Some(depth) = depth_texture {
+                    depth.clean(&self.context);
+                }
+                return Err(e);
+            }
+        };
+
+        if let Some(old) = self.textures.insert(texture_id, texture) {
+            old.clean(&self.context);
+        }
+
+        if let (Some(id), Some(depth)) = (depth_texture_id, depth_texture) {
+            if let Some(old) = self.textures.insert(id, depth) {
+                old.clean(&self.context);
+            }
+        }
+
+        if let Some(old) = self.render_targets.insert(render_texture, inner_rt) {
+            old.clean(&self.context);
+        }
+
+        Ok(())
+    }
+
+    fn pipeline_attributes(&self, pipeline: u64) -> Vec<AttributeInfo> {
+        self.pipelines
+            .get(&pipeline)
+            .map(|pip| pip.attributes.clone())
+            .unwrap_or_default()
+    }
+
+    fn is_format_renderable(&mut self, format: TextureFormat) -> bool {
+        if let Some(renderable) = self.renderable_formats.get(&format) {
+            return *renderable;
+        }
+
+        if format.is_compressed() {
+            return false;
+        }
+
+        let info = TextureInfo {
+            width: 1,
+            height: 1,
+            format,
+            ..Default::default()
+        };
+
+        // creating the texture changes the binding of the active texture slot
+        self.bound_textures = Default::default();
+        let renderable = match InnerTexture::new(&self.context, &info) {
+            Ok(texture) => {
+                let depth = format.is_depth();
+                let renderable = unsafe { is_renderable(&self.context, &texture, depth) };
+                texture.clean(&self.context);
+                renderable
+            }
+            Err(_) => false,
+        };
+
+        self.renderable_formats.insert(format, renderable);
+        renderable
+    }
+
+    fn copy_render_texture_to_texture(&mut self, src: u64, dst: u64) -> Result<(), String> {
+        let src = self.render_targets.get(&src).ok_or(format!(
+            "Error copying: render target id '{}' not found.",
+            src
+        ))?;
+        let dst = self
+            .textures
+            .get(&dst)
+            .ok_or(format!("Error copying: texture id '{}' not found.", dst))?;
+
+        if dst.target != gl::TEXTURE_2D {
+            return Err("Render textures can only be copied into 2d textures".to_string());
+        }
+
+        src.copy_to_texture(&self.context, dst);
+
+        // the copy changes the binding of the active texture slot
+        self.bound_textures = Default::default();
+        Ok(())
+    }
+
+    fn blit(
+        &mut self,
+        src: u64,
+        dst: u64,
+        src_rect: &Rect,
+        dst_rect: &Rect,
+        filter: &TextureFilter,
+    ) -> Result<(), String> {
+        let src = self.render_targets.get(&src).ok_or(format!(
+            "Error blitting: render target id '{}' not found.",
+            src
+        ))?;
+        let dst = self.render_targets.get(&dst).ok_or(format!(
+            "Error blitting: render target id '{}' not found.",
+            dst
+        ))?;
+
+        src.blit(&self.context, dst, src_rect, dst_rect, filter);
+        Ok(())
+    }
+
+    fn set_render_texture_face(&mut self, id: u64, face: CubeFace) -> Result<(), String> {
+        let rt = self.render_targets.get(&id).ok_or(format!(
+            "Error setting the cubemap face: render target id '{}' not found.",
+            id
+        ))?;
+
+        rt.set_face(&self.context, face)
+    }
+
     fn update_texture(&mut self, texture: u64, opts: &TextureUpdate) -> Result<(), String> {
+        if opts.format.is_compressed() {
+            return Err(format!("Cannot update a {:?} texture", opts.format));
+        }
+
         match self.textures.get(&texture) {
             Some(texture) => {
                 unsafe {
-                    gl::BindTexture(gl::TEXTURE_2D, texture.texture);
-                    gl::TexSubImage2D(
-                        gl::TEXTURE_2D,
-                        0,
-                        opts.x_offset,
-                        opts.y_offset,
-                        opts.width,
-                        opts.height,
-                        texture_format(&opts.format), // 3d texture needs another value?
-                        gl::UNSIGNED_BYTE,            // todo UNSIGNED SHORT FOR DEPTH (3d) TEXTURES
-                        opts.bytes.as_ptr() as *const _,
-                    );
+                    gl::BindTexture(texture.target, texture.texture);
+
+                    if let Some(row_length) = opts.row_length {
+                        gl::PixelStorei(gl::UNPACK_ROW_LENGTH, row_length);
+                    }
+
+                    if texture.target == gl::TEXTURE_CUBE_MAP {
+                        gl::TexSubImage2D(
+                            gl::TEXTURE_CUBE_MAP_POSITIVE_X + opts.layer,
+                            0,
+                            opts.x_offset,
+                            opts.y_offset,
+                            opts.width,
+                            opts.height,
+                            texture_format(&opts.format),
+                            texture_type(&opts.format),
+                            opts.bytes.as_ptr() as *const _,
+                        );
+                    } else if texture.target == gl::TEXTURE_2D_ARRAY {
+                        gl::TexSubImage3D(
+                            gl::TEXTURE_2D_ARRAY,
+                            0,
+                            opts.x_offset,
+                            opts.y_offset,
+                            opts.layer as _,
+                            opts.width,
+                            opts.height,
+                            1,
+                            texture_format(&opts.format),
+                            texture_type(&opts.format),
+                            opts.bytes.as_ptr() as *const _,
+                        );
+                    } else {
+                        gl::TexSubImage2D(
+                            gl::TEXTURE_2D,
+                            0,
+                            opts.x_offset,
+                            opts.y_offset,
+                            opts.width,
+                            opts.height,
+                            texture_format(&opts.format), // 3d texture needs another value?
+                            texture_type(&opts.format),
+                            opts.bytes.as_ptr() as *const _,
+                        );
+                    }
+
+                    if opts.row_length.is_some() {
+                        gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+                    }
                     // todo unbind texture?
                     Ok(())
                 }
@@ -556,17 +1767,25 @@ impl DeviceBackend for GlesBackend {
                 };
 
                 if can_read {
-                    gl::ReadPixels(
-                        opts.x_offset,
-                        opts.y_offset,
-                        opts.width,
-                        opts.height,
-                        texture_format(&opts.format),
-                        gl::UNSIGNED_BYTE,
-                        bytes.as_mut_ptr() as *mut _,
-                    );
+                    let format = texture_format(&opts.format);
+                    let typ = texture_type(&opts.format);
+                    let result = if is_readable(format, typ) {
+                        gl::ReadPixels(
+                            opts.x_offset,
+                            opts.y_offset,
+                            opts.width,
+                            opts.height,
+                            format,
+                            typ,
+                            bytes.as_mut_ptr() as *mut _,
+                        );
+                        Ok(())
+                    } else {
+                        // not readable directly, read it as RGBA and convert it
+                        read_pixels_converted(opts, bytes)
+                    };
                     clean();
-                    Ok(())
+                    result
                 } else {
                     clean();
                     Err("Framebuffer incomplete...".to_string())
@@ -576,16 +1795,162 @@ impl DeviceBackend for GlesBackend {
         }
     }
 
+    fn read_screen(&mut self, x: i32, y: i32, width: i32, height: i32, bytes: &mut [u8]) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                x,
+                y,
+                width,
+                height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                bytes.as_mut_ptr() as *mut _,
+            );
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        }
+    }
+
     fn swap_buffers(&mut self) {
         #[cfg(target_os = "linux")]
         egl::swap_buffers(self.display, self.surface);
 
         #[cfg(target_os = "windows")]
         self.context.swap_buffers();
+
+        self.last_stats = std::mem::take(&mut self.current_stats);
+    }
+
+    fn barrier(&mut self) {
+        self.written_textures.clear();
+        unsafe {
+            gl::Flush();
+        }
+    }
+
+    fn memory_barrier(&mut self, flags: BarrierFlags) {
+        if !gl::MemoryBarrier::is_loaded() {
+            eprintln!("Memory barriers are not supported by this context");
+            return;
+        }
+
+        unsafe {
+            gl::MemoryBarrier(flags.to_gl());
+        }
+    }
+
+    fn stats(&self) -> FrameStats {
+        self.last_stats
+    }
+
+    fn begin_occlusion_query(&mut self) -> Result<u64, String> {
+        let mut query = 0;
+        unsafe {
+            gl::GenQueries(1, &mut query as *mut _);
+            if query == 0 {
+                return Err("Failed to create the occlusion query".to_string());
+            }
+
+            gl::BeginQuery(gl::ANY_SAMPLES_PASSED, query);
+        }
+
+        self.query_count += 1;
+        self.queries.insert(self.query_count, query);
+        Ok(self.query_count)
+    }
+
+    fn end_occlusion_query(&mut self, query: u64) {
+        if self.queries.contains_key(&query) {
+            unsafe {
+                gl::EndQuery(gl::ANY_SAMPLES_PASSED);
+            }
+        }
+    }
+
+    fn occlusion_result(&mut self, query: u64) -> Option<bool> {
+        let query = *self.queries.get(&query)?;
+        unsafe {
+            let mut available: u32 = 0;
+            gl::GetQueryObjectuiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available as *mut _);
+            if available == 0 {
+                return None;
+            }
+
+            let mut result: u32 = 0;
+            gl::GetQueryObjectuiv(query, gl::QUERY_RESULT, &mut result as *mut _);
+            Some(result != 0)
+        }
+    }
+
+    fn delete_occlusion_query(&mut self, query: u64) {
+        if let Some(query) = self.queries.remove(&query) {
+            unsafe {
+                gl::DeleteQueries(1, &query as *const _);
+            }
+        }
+    }
+
+    fn insert_fence(&mut self) -> Result<u64, String> {
+        let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        if sync.is_null() {
+            return Err("Failed to create the fence".to_string());
+        }
+
+        self.fence_count += 1;
+        self.fences.insert(self.fence_count, sync);
+        Ok(self.fence_count)
+    }
+
+    fn wait_fence(&mut self, fence: u64, timeout: Duration) -> bool {
+        match self.fences.get(&fence) {
+            Some(sync) => unsafe {
+                let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+                let status = gl::ClientWaitSync(*sync, gl::SYNC_FLUSH_COMMANDS_BIT, timeout);
+                status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED
+            },
+            None => false,
+        }
+    }
+
+    fn is_fence_signaled(&mut self, fence: u64) -> bool {
+        match self.fences.get(&fence) {
+            Some(sync) => unsafe {
+                let mut status = 0;
+                gl::GetSynciv(*sync, gl::SYNC_STATUS, 1, std::ptr::null_mut(), &mut status);
+                status as u32 == gl::SIGNALED
+            },
+            None => false,
+        }
+    }
+
+    fn delete_fence(&mut self, fence: u64) {
+        if let Some(sync) = self.fences.remove(&fence) {
+            unsafe {
+                gl::DeleteSync(sync);
+            }
+        }
+    }
+
+    fn reset_gl_state(&mut self) {
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::STENCIL_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ColorMask(1, 1, 1, 1);
+        }
+    }
+
+    fn set_reset_gl_state_on_end(&mut self, enable: bool) {
+        self.reset_gl_state_on_end = enable;
     }
 }
 
 #[inline]
+/// Clears only depend on the write masks, enabling the depth and stencil tests
+/// is left to the pipelines so following draws keep the state they asked for
 pub(crate) fn clear(
     _context: &Context,
     color: &Option<Color>,
@@ -594,23 +1959,32 @@ pub(crate) fn clear(
 ) {
     let mut mask = 0;
     unsafe {
+        // the clears obey the masks, a depth prepass pipeline leaves the color ones off
         if let Some(color) = color {
             mask |= gl::COLOR_BUFFER_BIT;
+            gl::ColorMask(1, 1, 1, 1);
             gl::ClearColor(color.r, color.g, color.b, color.a);
         }
 
-        if let Some(depth) = *depth {
-            mask |= gl::DEPTH_BUFFER_BIT;
-            gl::Enable(gl::DEPTH_TEST);
-            gl::DepthMask(1);
-            gl::ClearDepthf(depth);
-        }
-
-        if let Some(stencil) = *stencil {
-            mask |= gl::STENCIL_BUFFER_BIT;
-            gl::Enable(gl::STENCIL_TEST);
-            gl::StencilMask(0xff);
-            gl::ClearStencil(stencil);
+        match (*depth, *stencil) {
+            // one call for combined depth stencil attachments,
+            // it's the same as clearing them separately otherwise
+            (Some(depth), Some(stencil)) => {
+                gl::DepthMask(1);
+                gl::StencilMask(0xff);
+                gl::ClearBufferfi(gl::DEPTH_STENCIL, 0, depth, stencil);
+            }
+            (Some(depth), None) => {
+                mask |= gl::DEPTH_BUFFER_BIT;
+                gl::DepthMask(1);
+                gl::ClearDepthf(depth);
+            }
+            (None, Some(stencil)) => {
+                mask |= gl::STENCIL_BUFFER_BIT;
+                gl::StencilMask(0xff);
+                gl::ClearStencil(stencil);
+            }
+            (None, None) => {}
         }
 
         if mask != 0 {
@@ -618,3 +1992,46 @@ pub(crate) fn clear(
         }
     }
 }
+
+/// Clear each color attachment to its own color
+pub(crate) fn clear_attachments(_context: &Context, colors: &[Color]) {
+    if !colors.is_empty() {
+        unsafe {
+            gl::ColorMask(1, 1, 1, 1);
+        }
+    }
+
+    colors.iter().enumerate().for_each(|(i, color)| unsafe {
+        gl::ClearBufferfv(gl::COLOR, i as _, color.rgba().as_ptr());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_window_handles_fail() {
+        let handle = RawWindowHandle::Web(raw_window_handle::WebHandle::empty());
+        assert!(GlesBackend::from_handle(handle, &BackendConfig::default()).is_err());
+    }
+
+    #[test]
+    fn index_offsets_in_bytes() {
+        assert_eq!(index_byte_offset(IndexFormat::UInt32, 0), 0);
+        assert_eq!(index_byte_offset(IndexFormat::UInt32, 6), 24);
+        assert_eq!(index_byte_offset(IndexFormat::UInt16, 6), 12);
+        assert_eq!(index_byte_offset(IndexFormat::UInt8, 6), 6);
+    }
+
+    #[test]
+    fn scissor_uses_the_target_height() {
+        // a 64x64 render texture while the window is 800x600 with a dpi of 2,
+        // the render texture passes use its own height and a dpi of 1
+        assert_eq!(scissor_rect(64, 1.0, 8.0, 0.0, 16.0, 32.0), (8, 32, 16, 32));
+        assert_eq!(
+            scissor_rect(600, 2.0, 8.0, 0.0, 16.0, 32.0),
+            (16, 1136, 32, 64)
+        );
+    }
+}
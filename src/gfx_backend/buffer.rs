@@ -1,26 +1,49 @@
-use super::{
-    gl,
-    pipeline::{InnerPipeline, VertexAttributes},
-    Context,
+use super::{gl, pipeline::VertexAttributes, Context};
+use crate::gfx::buffer::IndexFormat;
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CString},
 };
-use std::ffi::{c_void, CString};
 
 pub(crate) enum Kind {
     Vertex(VertexAttributes),
-    Index,
-    Uniform(u32, String),
+    Index(IndexFormat),
+    /// Slot and block name, checked for nul bytes on creation
+    Uniform(u32, CString),
+    Storage(u32, CString),
+}
+
+/// `glShaderStorageBlockBinding`, not part of GLES 3.1 so it's loaded when the driver exposes it
+pub(crate) type ShaderStorageBlockBindingFn = unsafe extern "system" fn(u32, u32, u32);
+
+/// Uniform or storage block index resolved for each pipeline a buffer was used with.
+/// The binding is set on the program, so it's done once per pipeline and stays valid
+/// when the pass switches to another pipeline and back
+#[derive(Debug, Default)]
+pub(crate) struct BlockIndices(HashMap<u64, u32>);
+
+impl BlockIndices {
+    /// Resolve and bind the block with `bind` the first time the pipeline uses the buffer
+    pub fn bind_once(&mut self, pipeline_id: u64, bind: impl FnOnce() -> u32) -> u32 {
+        *self.0.entry(pipeline_id).or_insert_with(bind)
+    }
+
+    /// Forget the index of a dropped pipeline
+    pub fn remove(&mut self, pipeline_id: u64) {
+        self.0.remove(&pipeline_id);
+    }
 }
 
 pub(crate) struct InnerBuffer {
     buffer: u32,
 
-    pub block_binded: bool,
+    /// Uniform or storage block index resolved for each pipeline the buffer was used with
+    pub block_indices: BlockIndices,
 
     gpu_buff_size: usize,
     draw_usage: u32,
     draw_target: u32,
     pub(crate) kind: Kind,
-    last_pipeline: Option<u64>,
 }
 
 impl InnerBuffer {
@@ -38,53 +61,100 @@ impl InnerBuffer {
 
         let draw_target = match &kind {
             Kind::Vertex(_) => gl::ARRAY_BUFFER,
-            Kind::Index => gl::ELEMENT_ARRAY_BUFFER,
+            Kind::Index(_) => gl::ELEMENT_ARRAY_BUFFER,
             Kind::Uniform(_, _) => gl::UNIFORM_BUFFER,
+            Kind::Storage(_, _) => gl::SHADER_STORAGE_BUFFER,
         };
 
         Ok(InnerBuffer {
             buffer,
 
-            block_binded: false,
+            block_indices: BlockIndices::default(),
 
             gpu_buff_size: 0,
             draw_usage,
             draw_target,
             kind,
-            last_pipeline: None,
         })
     }
 
     #[inline]
-    pub fn bind(&mut self, context: &Context, pipeline_id: Option<u64>) {
-        let pipeline_changed = pipeline_id.is_some() && pipeline_id != self.last_pipeline;
-        if pipeline_changed {
-            self.last_pipeline = pipeline_id;
-        };
-
+    pub fn bind(&self, _context: &Context) {
         unsafe {
             gl::BindBuffer(self.draw_target, self.buffer);
 
             match &self.kind {
-                Kind::Vertex(attrs) => {
-                    if pipeline_changed {
-                        attrs.enable(context);
-                    }
-                }
                 Kind::Uniform(slot, _) => {
                     gl::BindBufferBase(gl::UNIFORM_BUFFER, *slot, self.buffer);
                 }
+                Kind::Storage(slot, _) => {
+                    gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, *slot, self.buffer);
+                }
                 _ => {}
             }
         }
     }
 
+    /// Bind a range of a uniform buffer to the binding `slot`
+    pub fn bind_range(
+        &self,
+        _context: &Context,
+        slot: u32,
+        offset: u32,
+        size: u32,
+    ) -> Result<(), String> {
+        if !matches!(self.kind, Kind::Uniform(..)) {
+            return Err("Only uniform buffers can be bound by range".to_string());
+        }
+
+        let end = offset
+            .checked_add(size)
+            .ok_or_else(|| format!("Uniform range of {} bytes at {} overflows", size, offset))?;
+        if end as usize > self.gpu_buff_size {
+            return Err(format!(
+                "Uniform range {}..{} is out of the buffer of {} bytes",
+                offset, end, self.gpu_buff_size
+            ));
+        }
+
+        unsafe {
+            gl::BindBufferRange(
+                gl::UNIFORM_BUFFER,
+                slot,
+                self.buffer,
+                offset as _,
+                size as _,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Bind as the output of the transform feedback
+    #[inline]
+    pub fn bind_feedback(&self, _context: &Context) {
+        unsafe {
+            gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, 0, self.buffer);
+        }
+    }
+
+    /// Point the vertex attributes of the bound VAO to this buffer, it must be bound
+    #[inline]
+    pub fn enable_attrs(&self, context: &Context) {
+        if let Kind::Vertex(attrs) = &self.kind {
+            unsafe {
+                attrs.enable(context);
+            }
+        }
+    }
+
     #[inline]
     pub fn update(&mut self, _context: &Context, data: &[u8]) {
         let needs_alloc = self.gpu_buff_size != data.len();
 
         unsafe {
             if needs_alloc {
+                self.gpu_buff_size = data.len();
                 gl::BufferData(
                     self.draw_target,
                     data.len() as isize,
@@ -102,22 +172,116 @@ impl InnerBuffer {
         }
     }
 
-    pub fn bind_ubo_block(&mut self, _context: &Context, pipeline: &InnerPipeline) {
-        self.block_binded = true;
+    /// Allocate new storage before writing, so the draws still reading the old one
+    /// don't make the driver wait for them
+    #[inline]
+    pub fn update_orphaned(&mut self, _context: &Context, data: &[u8]) {
+        self.gpu_buff_size = data.len();
 
+        unsafe {
+            gl::BufferData(
+                self.draw_target,
+                data.len() as isize,
+                std::ptr::null(),
+                self.draw_usage,
+            );
+            gl::BufferSubData(
+                self.draw_target,
+                0,
+                data.len() as isize,
+                data.as_ptr() as *const c_void,
+            );
+        }
+    }
+
+    /// Upload a range of an allocated buffer, GL ignores the ones out of its storage
+    #[inline]
+    pub fn update_range(
+        &mut self,
+        _context: &Context,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), String> {
+        check_range(offset, data.len(), self.gpu_buff_size)?;
+
+        unsafe {
+            gl::BufferSubData(
+                self.draw_target,
+                offset as isize,
+                data.len() as isize,
+                data.as_ptr() as *const c_void,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_ubo_block(&mut self, _context: &Context, pipeline_id: u64, program: u32) {
         if let Kind::Uniform(slot, name) = &self.kind {
-            unsafe {
-                let name = CString::new(name.clone()).unwrap();
+            self.block_indices.bind_once(pipeline_id, || unsafe {
+                let index = gl::GetUniformBlockIndex(program, name.as_ptr());
 
-                let index = gl::GetUniformBlockIndex(pipeline.program, name.as_ptr());
+                if index != gl::INVALID_INDEX {
+                    gl::UniformBlockBinding(program, index, *slot);
+                }
+
+                index
+            });
+        }
+    }
+
+    pub fn bind_ssbo_block(
+        &mut self,
+        _context: &Context,
+        pipeline_id: u64,
+        program: u32,
+        block_binding: Option<ShaderStorageBlockBindingFn>,
+    ) {
+        if let Kind::Storage(slot, name) = &self.kind {
+            self.block_indices.bind_once(pipeline_id, || unsafe {
+                let index =
+                    gl::GetProgramResourceIndex(program, gl::SHADER_STORAGE_BLOCK, name.as_ptr());
 
                 if index != gl::INVALID_INDEX {
-                    gl::UniformBlockBinding(pipeline.program, index, *slot);
+                    match block_binding {
+                        Some(block_binding) => block_binding(program, index, *slot),
+                        None => {
+                            // without the entry point the binding declared in the shader is used
+                            let mut binding = 0;
+                            gl::GetProgramResourceiv(
+                                program,
+                                gl::SHADER_STORAGE_BLOCK,
+                                index,
+                                1,
+                                &gl::BUFFER_BINDING,
+                                1,
+                                std::ptr::null_mut(),
+                                &mut binding,
+                            );
+
+                            if binding as u32 != *slot {
+                                eprintln!(
+                                    "Storage block '{}' uses binding {} in the shader instead of the slot {}",
+                                    name.to_string_lossy(),
+                                    binding,
+                                    slot
+                                );
+                            }
+                        }
+                    }
                 }
-            }
+
+                index
+            });
         }
     }
 
+    /// Forget the block index resolved for a pipeline that was dropped
+    #[inline]
+    pub fn remove_pipeline(&mut self, pipeline_id: u64) {
+        self.block_indices.remove(pipeline_id);
+    }
+
     #[inline(always)]
     pub fn clean(self, _context: &Context) {
         unsafe {
@@ -125,3 +289,53 @@ impl InnerBuffer {
         }
     }
 }
+
+/// Check that `len` bytes at `offset` fit in a buffer of `size` bytes
+fn check_range(offset: usize, len: usize, size: usize) -> Result<(), String> {
+    match offset.checked_add(len) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(format!(
+            "Buffer range of {} bytes at {} is out of the allocated size {}",
+            len, offset, size
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_inside_the_buffer() {
+        assert!(check_range(0, 64, 64).is_ok());
+        assert!(check_range(32, 32, 64).is_ok());
+        assert!(check_range(32, 33, 64).is_err());
+        assert!(check_range(usize::MAX, 1, 64).is_err());
+    }
+
+    #[test]
+    fn block_bound_once_per_pipeline() {
+        let mut indices = BlockIndices::default();
+        let mut binds = vec![];
+
+        // the same uniform buffer used by two pipelines over three frames
+        for _ in 0..3 {
+            for (pipeline, index) in [(1, 0), (2, 3)] {
+                let bound = indices.bind_once(pipeline, || {
+                    binds.push(pipeline);
+                    index
+                });
+                assert_eq!(bound, index);
+            }
+        }
+        assert_eq!(binds, vec![1, 2]);
+
+        // the index of a dropped pipeline is forgotten
+        indices.remove(1);
+        indices.bind_once(1, || {
+            binds.push(1);
+            0
+        });
+        assert_eq!(binds, vec![1, 2, 1]);
+    }
+}
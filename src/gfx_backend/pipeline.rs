@@ -1,8 +1,13 @@
 use crate::gfx::{
     buffer::{VertexAttr, VertexStepMode},
-    pipeline::{BlendMode, CompareMode, PipelineOptions, StencilAction, StencilOptions},
+    pipeline::{
+        AttributeInfo, BlendMode, ColorMask, CompareMode, PipelineOptions, StencilAction,
+        StencilOptions,
+    },
 };
 
+use std::ffi::{CStr, CString};
+
 use super::{
     gl,
     to_gl::{ToGl, ToOptionalGl},
@@ -15,16 +20,19 @@ pub(crate) struct InnerPipeline {
     pub program: u32,
     pub vao: u32,
     pub uniform_locations: Vec<u32>,
+    pub attributes: Vec<AttributeInfo>,
 }
 
 #[inline]
 pub(crate) fn get_inner_attrs(attrs: &[VertexAttr]) -> (i32, Vec<InnerAttr>) {
+    let mut offset = 0;
     let mut stride = 0;
     let attrs = attrs
         .iter()
         .map(|attr| {
-            let inner_attr = InnerAttr::from(attr, stride);
-            stride += attr.format.bytes();
+            let inner_attr = InnerAttr::from(attr, attr.offset.map_or(offset, |o| o as i32));
+            offset = inner_attr.offset + attr.format.bytes();
+            stride = stride.max(offset);
             inner_attr
         })
         .collect::<Vec<_>>();
@@ -39,10 +47,54 @@ impl InnerPipeline {
         vertex_source: &str,
         fragment_source: &str,
         attrs: &[VertexAttr],
+        feedback_varyings: &[String],
     ) -> Result<Self, String> {
-        let (stride, attrs) = get_inner_attrs(attrs);
+        Self::start(
+            context,
+            vertex_source,
+            fragment_source,
+            attrs,
+            feedback_varyings,
+        )?
+        .finish(context)
+    }
+
+    /// Send the shaders to compile and the program to link without waiting for them
+    pub fn start(
+        context: &Context,
+        vertex_source: &str,
+        fragment_source: &str,
+        attrs: &[VertexAttr],
+        feedback_varyings: &[String],
+    ) -> Result<PendingPipeline, String> {
+        drain_gl_errors("before creating a pipeline");
+
+        let attr_names = attrs
+            .iter()
+            .filter_map(|attr| attr.name.map(|name| (attr.location, name)))
+            .collect::<Vec<_>>();
+
+        let vertex = compile_shader(context, gl::VERTEX_SHADER, vertex_source);
+        let fragment = compile_shader(context, gl::FRAGMENT_SHADER, fragment_source);
+        let program =
+            match link_program(context, &[vertex, fragment], &attr_names, feedback_varyings) {
+                Ok(program) => program,
+                Err(err) => {
+                    unsafe {
+                        gl::DeleteShader(vertex);
+                        gl::DeleteShader(fragment);
+                    }
+                    return Err(err);
+                }
+            };
 
-        create_pipeline(context, vertex_source, fragment_source, stride, attrs)
+        Ok(PendingPipeline {
+            vertex,
+            fragment,
+            program,
+            vertex_source: vertex_source.to_string(),
+            fragment_source: fragment_source.to_string(),
+        })
     }
 
     #[inline(always)]
@@ -51,7 +103,7 @@ impl InnerPipeline {
     }
 
     #[inline(always)]
-    pub fn bind(&self, context: &Context, options: &PipelineOptions) {
+    pub fn bind(&self, context: &Context, options: &PipelineOptions, fns: &PipelineFns) {
         unsafe {
             gl::BindVertexArray(self.vao);
             gl::UseProgram(self.program);
@@ -59,22 +111,111 @@ impl InnerPipeline {
             set_stencil(context, options);
             set_depth_stencil(context, options);
             set_color_mask(context, options);
+            set_alpha_to_coverage(context, options);
             set_culling(context, options);
-            set_blend_mode(context, options);
+            set_sample_shading(context, options, fns.min_sample_shading);
+            match (&options.attachment_blends, fns.indexed_blend) {
+                (Some(blends), Some(fns)) if blends.len() > 1 => {
+                    set_attachment_blend_modes(context, blends, fns)
+                }
+                _ => set_blend_mode(context, options),
+            }
+        }
+    }
+}
+
+/// Entry points used by the pipeline state that aren't part of GLES 3.1
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PipelineFns {
+    pub indexed_blend: Option<IndexedBlendFns>,
+    pub min_sample_shading: Option<MinSampleShadingFn>,
+}
+
+/// `glBlendFunci` and `glBlendEquationi`, from GLES 3.2 or `OES_draw_buffers_indexed`
+#[derive(Clone, Copy)]
+pub(crate) struct IndexedBlendFns {
+    pub blend_func: unsafe extern "system" fn(u32, u32, u32),
+    pub blend_equation: unsafe extern "system" fn(u32, u32),
+}
+
+/// `glMinSampleShading`, from GLES 3.2 or `OES_sample_shading`
+pub(crate) type MinSampleShadingFn = unsafe extern "system" fn(f32);
+
+/// `GL_SAMPLE_SHADING`, not part of GLES 3.1
+const SAMPLE_SHADING: u32 = 0x8C36;
+
+pub(crate) struct InnerComputePipeline {
+    pub shader: u32,
+    pub program: u32,
+    pub uniform_locations: Vec<u32>,
+}
+
+impl InnerComputePipeline {
+    pub fn new(context: &Context, source: &str) -> Result<Self, String> {
+        drain_gl_errors("before creating a compute pipeline");
+
+        let shader = create_shader(context, gl::COMPUTE_SHADER, source)?;
+        let program = match create_program(context, &[shader], &[], &[]) {
+            Ok(program) => program,
+            Err(err) => {
+                unsafe {
+                    gl::DeleteShader(shader);
+                }
+                return Err(err);
+            }
+        };
+
+        let uniform_locations = get_uniform_locations(program);
+        drain_gl_errors("creating a compute pipeline");
+
+        Ok(Self {
+            shader,
+            program,
+            uniform_locations,
+        })
+    }
+
+    #[inline(always)]
+    pub fn clean(self, _context: &Context) {
+        unsafe {
+            gl::DeleteShader(self.shader);
+            gl::DeleteProgram(self.program);
+        }
+    }
+
+    #[inline(always)]
+    pub fn bind(&self, _context: &Context) {
+        unsafe {
+            gl::UseProgram(self.program);
+        }
+    }
+
+    #[inline(always)]
+    pub fn dispatch(&self, _context: &Context, x: u32, y: u32, z: u32) {
+        unsafe {
+            gl::DispatchCompute(x, y, z);
+            gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct VertexAttributes {
+    pub slot: u32,
     pub stride: i32,
     attrs: Vec<InnerAttr>,
     vertex_step_mode: VertexStepMode,
 }
 
 impl VertexAttributes {
-    pub fn new(stride: i32, attrs: Vec<InnerAttr>, vertex_step_mode: VertexStepMode) -> Self {
+    pub fn new(
+        slot: u32,
+        stride: i32,
+        attrs: Vec<InnerAttr>,
+        vertex_step_mode: VertexStepMode,
+    ) -> Self {
         Self {
+            slot,
             stride,
             attrs,
             vertex_step_mode,
@@ -85,6 +226,7 @@ impl VertexAttributes {
         let step_mode = match self.vertex_step_mode {
             VertexStepMode::Vertex => 0,
             VertexStepMode::Instance => 1,
+            VertexStepMode::InstanceEvery(divisor) => divisor,
         };
 
         self.attrs
@@ -164,12 +306,43 @@ unsafe fn set_depth_stencil(_context: &Context, options: &PipelineOptions) {
 
 #[inline(always)]
 unsafe fn set_color_mask(_context: &Context, options: &PipelineOptions) {
-    gl::ColorMask(
-        options.color_mask.r as _,
-        options.color_mask.g as _,
-        options.color_mask.b as _,
-        options.color_mask.a as _,
-    );
+    let [r, g, b, a] = color_mask_args(&options.color_mask);
+    gl::ColorMask(r, g, b, a);
+}
+
+/// Arguments of `glColorMask` for the mask
+#[inline(always)]
+fn color_mask_args(mask: &ColorMask) -> [u8; 4] {
+    [mask.r as _, mask.g as _, mask.b as _, mask.a as _]
+}
+
+#[inline(always)]
+unsafe fn set_alpha_to_coverage(_context: &Context, options: &PipelineOptions) {
+    if options.alpha_to_coverage {
+        gl::Enable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+    } else {
+        gl::Disable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+    }
+}
+
+#[inline(always)]
+unsafe fn set_sample_shading(
+    _context: &Context,
+    options: &PipelineOptions,
+    min_sample_shading: Option<MinSampleShadingFn>,
+) {
+    let min_sample_shading = match min_sample_shading {
+        Some(f) => f,
+        None => return,
+    };
+
+    match options.min_sample_shading {
+        Some(value) => {
+            gl::Enable(SAMPLE_SHADING);
+            min_sample_shading(value);
+        }
+        None => gl::Disable(SAMPLE_SHADING),
+    }
 }
 
 #[inline(always)]
@@ -185,7 +358,13 @@ unsafe fn set_culling(_context: &Context, options: &PipelineOptions) {
 
 #[inline(always)]
 unsafe fn set_blend_mode(_context: &Context, options: &PipelineOptions) {
-    match (options.color_blend, options.alpha_blend) {
+    // a single attachment mode doesn't need the indexed calls
+    let single = match &options.attachment_blends {
+        Some(blends) if blends.len() == 1 => Some(blends[0]),
+        _ => None,
+    };
+
+    match (single.or(options.color_blend), options.alpha_blend) {
         (Some(cbm), None) => {
             gl::Enable(gl::BLEND);
             gl::BlendFunc(cbm.src.to_gl(), cbm.dst.to_gl());
@@ -218,6 +397,19 @@ unsafe fn set_blend_mode(_context: &Context, options: &PipelineOptions) {
     }
 }
 
+#[inline(always)]
+unsafe fn set_attachment_blend_modes(
+    _context: &Context,
+    blends: &[BlendMode],
+    fns: IndexedBlendFns,
+) {
+    gl::Enable(gl::BLEND);
+    blends.iter().enumerate().for_each(|(i, bm)| {
+        (fns.blend_func)(i as _, bm.src.to_gl(), bm.dst.to_gl());
+        (fns.blend_equation)(i as _, bm.op.to_gl());
+    });
+}
+
 #[inline(always)]
 fn clean_pipeline(_context: &Context, pip: InnerPipeline) {
     let InnerPipeline {
@@ -236,19 +428,152 @@ fn clean_pipeline(_context: &Context, pip: InnerPipeline) {
     }
 }
 
+/// `GL_COMPLETION_STATUS_KHR` of `KHR_parallel_shader_compile`
+pub(crate) const COMPLETION_STATUS_KHR: u32 = 0x91B1;
+
+/// Pipeline with its shaders compiling and its program linking. The status is only
+/// checked on `finish`, so the driver can work on many pipelines at the same time
+pub(crate) struct PendingPipeline {
+    vertex: u32,
+    fragment: u32,
+    program: u32,
+    vertex_source: String,
+    fragment_source: String,
+}
+
+impl PendingPipeline {
+    /// The program finished linking, always true without `KHR_parallel_shader_compile`
+    pub fn is_ready(&self, parallel_compile: bool) -> bool {
+        if !parallel_compile {
+            return true;
+        }
+
+        let mut status = 0;
+        unsafe {
+            gl::GetProgramiv(self.program, COMPLETION_STATUS_KHR, &mut status);
+        }
+        status != 0
+    }
+
+    /// Check the compilation and link, blocking until they are done
+    pub fn finish(self, _context: &Context) -> Result<InnerPipeline, String> {
+        let Self {
+            vertex,
+            fragment,
+            program,
+            vertex_source,
+            fragment_source,
+        } = self;
+
+        let status = check_shader(vertex, gl::VERTEX_SHADER, &vertex_source)
+            .and_then(|_| check_shader(fragment, gl::FRAGMENT_SHADER, &fragment_source))
+            .and_then(|_| check_program(program));
+
+        if let Err(err) = status {
+            unsafe {
+                gl::DeleteShader(vertex);
+                gl::DeleteShader(fragment);
+                gl::DeleteProgram(program);
+            }
+            return Err(err);
+        }
+
+        let uniform_locations = get_uniform_locations(program);
+        let attributes = get_active_attributes(program);
+
+        let vao = unsafe {
+            let mut vao = 0;
+            gl::GenVertexArrays(1, &mut vao as *mut _);
+            gl::BindVertexArray(vao);
+            vao
+        };
+
+        drain_gl_errors("creating a pipeline");
+
+        Ok(InnerPipeline {
+            vertex,
+            fragment,
+            program,
+            vao,
+            uniform_locations,
+            attributes,
+        })
+    }
+}
+
+/// Log and clear the pending `glGetError` flags, so an error left by an earlier call
+/// isn't attributed to the next one. Returns how many were pending
+pub(crate) fn drain_gl_errors(when: &str) -> usize {
+    // bounded, a lost context can keep returning an error
+    (0..16)
+        .map(|_| unsafe { gl::GetError() })
+        .take_while(|err| *err != gl::NO_ERROR)
+        .inspect(|err| eprintln!("GL error 0x{:x} {}", err, when))
+        .count()
+}
+
 #[inline(always)]
-fn create_pipeline(
-    context: &Context,
-    vertex_source: &str,
-    fragment_source: &str,
-    _stride: i32,
-    _attrs: Vec<InnerAttr>,
-) -> Result<InnerPipeline, String> {
-    let vertex = create_shader(context, gl::VERTEX_SHADER, vertex_source)?;
-    let fragment = create_shader(context, gl::FRAGMENT_SHADER, fragment_source)?;
-    let program = create_program(context, vertex, fragment)?;
-
-    let uniform_locations = unsafe {
+fn get_active_attributes(program: u32) -> Vec<AttributeInfo> {
+    unsafe {
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+
+        let mut max_length = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+
+        (0..count)
+            .filter_map(|index| {
+                let mut name = vec![0u8; max_length as usize];
+                let mut length = 0;
+                let mut size = 0;
+                let mut typ = 0;
+                gl::GetActiveAttrib(
+                    program,
+                    index as _,
+                    max_length,
+                    &mut length,
+                    &mut size,
+                    &mut typ,
+                    name.as_mut_ptr() as *mut _,
+                );
+
+                attribute_info(name, length, size, typ, |c_name| {
+                    gl::GetAttribLocation(program, c_name.as_ptr())
+                })
+            })
+            .collect()
+    }
+}
+
+/// Build the info of an active attribute from the name buffer filled by `glGetActiveAttrib`
+/// and its written `length`, `location` resolves the name like `glGetAttribLocation`
+fn attribute_info(
+    mut name: Vec<u8>,
+    length: i32,
+    size: i32,
+    typ: u32,
+    location: impl FnOnce(&CStr) -> i32,
+) -> Option<AttributeInfo> {
+    name.truncate(length.max(0) as usize);
+
+    // built-ins like gl_VertexID are active but don't have a location
+    let c_name = CString::new(name).ok()?;
+    let location = location(&c_name);
+    if location < 0 {
+        return None;
+    }
+
+    Some(AttributeInfo {
+        name: c_name.into_string().ok()?,
+        location: location as _,
+        size,
+        typ,
+    })
+}
+
+#[inline(always)]
+fn get_uniform_locations(program: u32) -> Vec<u32> {
+    unsafe {
         let mut count = 0;
         gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
 
@@ -290,26 +615,24 @@ fn create_pipeline(
                 }
             })
             .collect::<Vec<_>>()
-    };
+    }
+}
 
-    let vao = unsafe {
-        let mut vao = 0;
-        gl::GenVertexArrays(1, &mut vao as *mut _);
-        gl::BindVertexArray(vao);
-        vao
-    };
+#[inline(always)]
+fn create_shader(context: &Context, typ: u32, source: &str) -> Result<u32, String> {
+    let shader = compile_shader(context, typ, source);
+    if let Err(err) = check_shader(shader, typ, source) {
+        unsafe {
+            gl::DeleteShader(shader);
+        }
+        return Err(err);
+    }
 
-    Ok(InnerPipeline {
-        vertex,
-        fragment,
-        program,
-        vao,
-        uniform_locations,
-    })
+    Ok(shader)
 }
 
 #[inline(always)]
-fn create_shader(_context: &Context, typ: u32, source: &str) -> Result<u32, String> {
+fn compile_shader(_context: &Context, typ: u32, source: &str) -> u32 {
     unsafe {
         let shader = gl::CreateShader(typ);
         gl::ShaderSource(
@@ -319,11 +642,17 @@ fn create_shader(_context: &Context, typ: u32, source: &str) -> Result<u32, Stri
             &(source.len() as _),
         );
         gl::CompileShader(shader);
+        shader
+    }
+}
 
+/// Check the compile status, returning the info log if it failed
+fn check_shader(shader: u32, typ: u32, source: &str) -> Result<(), String> {
+    unsafe {
         let mut status = 0;
         gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status as *mut _);
         if status == 1 {
-            return Ok(shader);
+            return Ok(());
         }
 
         let err = {
@@ -339,11 +668,11 @@ fn create_shader(_context: &Context, typ: u32, source: &str) -> Result<u32, Stri
                 String::from("")
             }
         };
-        gl::DeleteShader(shader);
 
         let typ_name = match typ {
             gl::VERTEX_SHADER => "vertex".to_string(),
             gl::FRAGMENT_SHADER => "fragment".to_string(),
+            gl::COMPUTE_SHADER => "compute".to_string(),
             _ => format!("unknown type ({})", typ),
         };
 
@@ -355,17 +684,77 @@ fn create_shader(_context: &Context, typ: u32, source: &str) -> Result<u32, Stri
 }
 
 #[inline(always)]
-fn create_program(_context: &Context, vertex: u32, fragment: u32) -> Result<u32, String> {
+fn create_program(
+    context: &Context,
+    shaders: &[u32],
+    attr_names: &[(u32, &str)],
+    feedback_varyings: &[String],
+) -> Result<u32, String> {
+    let program = link_program(context, shaders, attr_names, feedback_varyings)?;
+    if let Err(err) = check_program(program) {
+        unsafe {
+            gl::DeleteProgram(program);
+        }
+        return Err(err);
+    }
+
+    Ok(program)
+}
+
+#[inline(always)]
+fn link_program(
+    _context: &Context,
+    shaders: &[u32],
+    attr_names: &[(u32, &str)],
+    feedback_varyings: &[String],
+) -> Result<u32, String> {
+    let attr_names = attr_names
+        .iter()
+        .map(|(location, name)| Ok((*location, CString::new(*name)?)))
+        .collect::<Result<Vec<_>, std::ffi::NulError>>()
+        .map_err(|e| e.to_string())?;
+    let varyings = feedback_varyings
+        .iter()
+        .map(|name| CString::new(name.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
     unsafe {
         let program = gl::CreateProgram();
-        gl::AttachShader(program, vertex);
-        gl::AttachShader(program, fragment);
+        shaders
+            .iter()
+            .for_each(|shader| gl::AttachShader(program, *shader));
+
+        for (location, name) in &attr_names {
+            gl::BindAttribLocation(program, *location, name.as_ptr());
+        }
+
+        // transform feedback outputs are part of the program layout, set before linking
+        if !varyings.is_empty() {
+            let ptrs = varyings
+                .iter()
+                .map(|name| name.as_ptr())
+                .collect::<Vec<_>>();
+            gl::TransformFeedbackVaryings(
+                program,
+                ptrs.len() as _,
+                ptrs.as_ptr(),
+                gl::INTERLEAVED_ATTRIBS,
+            );
+        }
+
         gl::LinkProgram(program);
+        Ok(program)
+    }
+}
 
+/// Check the link status, returning the info log if it failed
+fn check_program(program: u32) -> Result<(), String> {
+    unsafe {
         let mut status = 0;
         gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
         if status == 1 {
-            return Ok(program);
+            return Ok(());
         }
 
         let err = {
@@ -381,7 +770,6 @@ fn create_program(_context: &Context, vertex: u32, fragment: u32) -> Result<u32,
                 String::from("")
             }
         };
-        gl::DeleteProgram(program);
         Err(err)
     }
 }
@@ -398,3 +786,60 @@ fn should_disable_stencil(stencil: &Option<StencilOptions>) -> bool {
         None => true,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name buffer of `max_length` bytes like the one filled by `glGetActiveAttrib`
+    fn name_buffer(name: &str, max_length: usize) -> (Vec<u8>, i32) {
+        let mut buffer = vec![0u8; max_length];
+        buffer[..name.len()].copy_from_slice(name.as_bytes());
+        (buffer, name.len() as _)
+    }
+
+    #[test]
+    fn active_attributes_of_two_inputs() {
+        let locations = [("a_pos", 0), ("a_color", 1), ("gl_VertexID", -1)];
+        let location = |name: &CStr| {
+            locations
+                .iter()
+                .find(|(n, _)| n.as_bytes() == name.to_bytes())
+                .map_or(-1, |(_, location)| *location)
+        };
+
+        let (name, length) = name_buffer("a_pos", 12);
+        assert_eq!(
+            attribute_info(name, length, 1, gl::FLOAT_VEC2, location),
+            Some(AttributeInfo {
+                name: "a_pos".to_string(),
+                location: 0,
+                size: 1,
+                typ: gl::FLOAT_VEC2,
+            })
+        );
+
+        let (name, length) = name_buffer("a_color", 12);
+        assert_eq!(
+            attribute_info(name, length, 1, gl::FLOAT_VEC4, location),
+            Some(AttributeInfo {
+                name: "a_color".to_string(),
+                location: 1,
+                size: 1,
+                typ: gl::FLOAT_VEC4,
+            })
+        );
+
+        let (name, length) = name_buffer("gl_VertexID", 12);
+        assert_eq!(attribute_info(name, length, 1, gl::INT, location), None);
+    }
+
+    #[test]
+    fn color_mask_presets() {
+        assert_eq!(color_mask_args(&ColorMask::ALL), [1, 1, 1, 1]);
+        assert_eq!(color_mask_args(&ColorMask::NONE), [0, 0, 0, 0]);
+        assert_eq!(color_mask_args(&ColorMask::RGB_ONLY), [1, 1, 1, 0]);
+        assert_eq!(color_mask_args(&ColorMask::ALPHA_ONLY), [0, 0, 0, 1]);
+        assert_eq!(ColorMask::default(), ColorMask::ALL);
+    }
+}
@@ -1,50 +1,89 @@
 use super::{
     clear,
     gl::{self},
-    texture::{create_texture, InnerTexture},
+    texture::InnerTexture,
+    to_gl::ToGl,
     Context,
 };
 use crate::gfx::{
     color::Color,
-    texture::{TextureFilter, TextureFormat, TextureInfo},
+    rect::Rect,
+    texture::{CubeFace, TextureFilter, TextureInfo},
 };
 
+/// Framebuffer of a render texture, the color and depth textures are owned by the backend
 pub(crate) struct InnerRenderTexture {
     fbo: u32,
-    depth_texture: Option<u32>,
     pub size: (i32, i32),
+    /// Id of the color texture in the backend textures
+    pub texture_id: u64,
+    /// Id of the depth texture in the backend textures
+    pub depth_texture_id: Option<u64>,
+    /// Color attachments of the framebuffer, the outputs `set_draw_buffers` can enable
+    pub color_attachments: usize,
+    texture: u32,
+    texture_target: u32,
+    mip_level: u32,
 }
 
 impl InnerRenderTexture {
     pub fn new(
         context: &Context,
+        texture_id: u64,
         texture: &InnerTexture,
+        depth_texture: Option<(u64, &InnerTexture)>,
         info: &TextureInfo,
     ) -> Result<Self, String> {
-        let width = info.width;
-        let height = info.height;
-        let depth_info = if info.depth {
-            Some(DepthInfo { width, height })
-        } else {
-            None
-        };
+        let width = (texture.size.0 >> info.mip_level).max(1);
+        let height = (texture.size.1 >> info.mip_level).max(1);
 
-        let (fbo, depth_texture) = unsafe { create_fbo(context, texture.texture, depth_info)? };
-        let size = texture.size;
+        let fbo = unsafe {
+            create_fbo(
+                context,
+                texture.texture,
+                attachment_target(texture.target),
+                info.mip_level,
+                depth_texture.map(|(_, t)| t.texture),
+            )?
+        };
+        let size = (width, height);
         Ok(Self {
             fbo,
-            depth_texture,
             size,
+            texture_id,
+            depth_texture_id: depth_texture.map(|(id, _)| id),
+            color_attachments: 1,
+            texture: texture.texture,
+            texture_target: texture.target,
+            mip_level: info.mip_level,
         })
     }
 
+    /// Attach a face of the cubemap as the color attachment
+    pub fn set_face(&self, _context: &Context, face: CubeFace) -> Result<(), String> {
+        if self.texture_target != gl::TEXTURE_CUBE_MAP {
+            return Err("The render target doesn't have a cubemap".to_string());
+        }
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                self.texture,
+                self.mip_level as _,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        Ok(())
+    }
+
     #[inline(always)]
     pub fn clean(&self, _context: &Context) {
         unsafe {
             gl::DeleteFramebuffers(1, &self.fbo as *const _);
-            if let Some(tex) = self.depth_texture {
-                gl::DeleteTextures(1, &tex as *const _);
-            }
         }
     }
 
@@ -54,41 +93,87 @@ impl InnerRenderTexture {
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
         }
     }
+
+    /// Copy the color attachment into the level 0 of a 2d texture
+    pub fn copy_to_texture(&self, _context: &Context, texture: &InnerTexture) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindTexture(gl::TEXTURE_2D, texture.texture);
+            gl::CopyTexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, 0, 0, self.size.0, self.size.1);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    pub fn blit(
+        &self,
+        _context: &Context,
+        dst: &InnerRenderTexture,
+        src_rect: &Rect,
+        dst_rect: &Rect,
+        filter: &TextureFilter,
+    ) {
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, dst.fbo);
+            gl::BlitFramebuffer(
+                src_rect.x as _,
+                src_rect.y as _,
+                (src_rect.x + src_rect.width) as _,
+                (src_rect.y + src_rect.height) as _,
+                dst_rect.x as _,
+                dst_rect.y as _,
+                (dst_rect.x + dst_rect.width) as _,
+                (dst_rect.y + dst_rect.height) as _,
+                gl::COLOR_BUFFER_BIT,
+                filter.to_gl(),
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+}
+
+/// Target used to attach a texture, the first face for cubemaps
+#[inline]
+fn attachment_target(texture_target: u32) -> u32 {
+    match texture_target {
+        gl::TEXTURE_CUBE_MAP => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+        target => target,
+    }
 }
 
 unsafe fn create_fbo(
     context: &Context,
     texture: u32,
-    depth_info: Option<DepthInfo>,
-) -> Result<(u32, Option<u32>), String> {
+    texture_target: u32,
+    mip_level: u32,
+    depth_texture: Option<u32>,
+) -> Result<u32, String> {
     let mut fbo = 0;
     gl::GenFramebuffers(1, &mut fbo as *mut _);
     gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
     gl::FramebufferTexture2D(
         gl::FRAMEBUFFER,
         gl::COLOR_ATTACHMENT0,
-        gl::TEXTURE_2D,
+        texture_target,
         texture,
-        0,
+        mip_level as _,
     );
 
-    let depth_texture = match depth_info {
-        Some(info) => Some(create_texture(
-            context,
-            &TextureInfo {
-                width: info.width,
-                height: info.height,
-                format: TextureFormat::Depth16,
-                min_filter: TextureFilter::Linear,
-                mag_filter: TextureFilter::Linear,
-                ..Default::default()
-            },
-        )?),
-        _ => None,
-    };
+    if let Some(depth_texture) = depth_texture {
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::TEXTURE_2D,
+            depth_texture,
+            0,
+        );
+    }
 
     let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
     if status != gl::FRAMEBUFFER_COMPLETE {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &fbo as *const _);
         return Err(
             "Cannot create a render target because the frambuffer is incomplete...".to_string(),
         );
@@ -98,10 +183,35 @@ unsafe fn create_fbo(
     clear(context, &Some(Color::TRANSPARENT), &None, &None);
 
     gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-    Ok((fbo, depth_texture))
+    Ok(fbo)
 }
 
-struct DepthInfo {
-    width: i32,
-    height: i32,
+/// Check if a texture can be attached to a framebuffer, depth formats as the depth attachment
+pub(crate) unsafe fn is_renderable(
+    _context: &Context,
+    texture: &InnerTexture,
+    depth: bool,
+) -> bool {
+    let attachment = if depth {
+        gl::DEPTH_ATTACHMENT
+    } else {
+        gl::COLOR_ATTACHMENT0
+    };
+
+    let mut fbo = 0;
+    gl::GenFramebuffers(1, &mut fbo as *mut _);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+    gl::FramebufferTexture2D(
+        gl::FRAMEBUFFER,
+        attachment,
+        gl::TEXTURE_2D,
+        texture.texture,
+        0,
+    );
+
+    let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+
+    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    gl::DeleteFramebuffers(1, &fbo as *const _);
+    status == gl::FRAMEBUFFER_COMPLETE
 }
@@ -1,26 +1,39 @@
 use std::ptr;
 
 use super::{gl, to_gl::ToGl, Context};
-use crate::gfx::texture::{TextureFormat, TextureInfo};
+use crate::gfx::{
+    sampler::SamplerInfo,
+    texture::{TextureFilter, TextureFormat, TextureInfo, TextureKind, TextureRead},
+};
 
 pub type TextureKey = u32;
 
 pub(crate) struct InnerTexture {
     pub texture: TextureKey,
     pub size: (i32, i32),
+    /// `TEXTURE_2D`, `TEXTURE_2D_ARRAY` or `TEXTURE_CUBE_MAP`
+    pub target: u32,
+    /// Created with `TexStorage`, it can be bound as an image
+    pub immutable: bool,
 }
 
 impl InnerTexture {
     pub fn new(context: &Context, info: &TextureInfo) -> Result<Self, String> {
         let texture = unsafe { create_texture(context, info)? };
         let size = (info.width, info.height);
-        Ok(Self { texture, size })
+        let target = texture_target(&info.kind);
+        Ok(Self {
+            texture,
+            size,
+            target,
+            immutable: info.image_storage,
+        })
     }
 
     pub fn bind(&self, _context: &Context, slot: u32, location: &u32) {
         unsafe {
             gl::ActiveTexture(gl_slot(slot).unwrap());
-            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::BindTexture(self.target, self.texture);
             gl::Uniform1i(*location as _, slot as _);
         }
     }
@@ -56,44 +69,33 @@ pub(crate) unsafe fn create_texture(
     gl::GenTextures(1, &mut texture as *mut _);
 
     let bytes_per_pixel = info.bytes_per_pixel();
-    if bytes_per_pixel != 4 {
+    if (1..4).contains(&bytes_per_pixel) {
         gl::PixelStorei(gl::UNPACK_ALIGNMENT, bytes_per_pixel as _);
     }
 
-    gl::BindTexture(gl::TEXTURE_2D, texture);
+    let target = texture_target(&info.kind);
+    gl::BindTexture(target, texture);
 
-    gl::TexParameteri(
-        gl::TEXTURE_2D,
-        gl::TEXTURE_MAG_FILTER,
-        info.mag_filter.to_gl() as _,
-    );
-    gl::TexParameteri(
-        gl::TEXTURE_2D,
-        gl::TEXTURE_MIN_FILTER,
-        info.min_filter.to_gl() as _,
-    );
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+    gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, info.mag_filter.to_gl() as _);
+    gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter(info) as _);
+    gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+    gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+
+    let [r, g, b, a] = info.swizzle;
+    gl::TexParameteri(target, gl::TEXTURE_SWIZZLE_R, r.to_gl() as _);
+    gl::TexParameteri(target, gl::TEXTURE_SWIZZLE_G, g.to_gl() as _);
+    gl::TexParameteri(target, gl::TEXTURE_SWIZZLE_B, b.to_gl() as _);
+    gl::TexParameteri(target, gl::TEXTURE_SWIZZLE_A, a.to_gl() as _);
 
-    let depth = TextureFormat::Depth16 == info.format;
+    let depth = info.format.is_depth();
     let mut data = info.bytes.as_deref();
-    let mut typ = gl::UNSIGNED_BYTE;
-    let mut format = texture_format(&info.format);
+    let typ = texture_type(&info.format);
+    let format = texture_format(&info.format);
     if depth {
-        format = gl::DEPTH_COMPONENT;
-        typ = gl::UNSIGNED_SHORT;
         data = None;
 
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
-
-        gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER,
-            gl::DEPTH_ATTACHMENT,
-            gl::TEXTURE_2D,
-            texture,
-            0,
-        );
     }
 
     let mut c_data = ptr::null();
@@ -101,34 +103,376 @@ pub(crate) unsafe fn create_texture(
         c_data = data.as_ptr();
     }
 
-    gl::TexImage2D(
-        gl::TEXTURE_2D,
-        0,
-        texture_internal_format(&info.format) as _,
-        info.width,
-        info.height,
-        0,
-        format,
-        typ,
-        c_data as *const _,
-    );
+    let compressed_size = info
+        .format
+        .compressed_size(info.width, info.height)
+        .map(|size| (size * info.layers() as usize) as i32);
+
+    match (info.kind, compressed_size) {
+        _ if info.image_storage => texture_storage(info, target, c_data, format, typ),
+        (TextureKind::Cube, _) => {
+            let face_size = compressed_size.map_or(
+                (info.width * info.height) as usize * bytes_per_pixel as usize,
+                |size| size as usize / 6,
+            );
+
+            for face in 0..6 {
+                let face_target = gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32;
+                let face_data = if c_data.is_null() {
+                    c_data
+                } else {
+                    c_data.add(face * face_size)
+                };
 
-    gl::BindTexture(gl::TEXTURE_2D, 0);
+                match compressed_size {
+                    Some(_) => gl::CompressedTexImage2D(
+                        face_target,
+                        0,
+                        texture_internal_format(&info.format),
+                        info.width,
+                        info.height,
+                        0,
+                        face_size as _,
+                        face_data as *const _,
+                    ),
+                    None => gl::TexImage2D(
+                        face_target,
+                        0,
+                        texture_internal_format(&info.format) as _,
+                        info.width,
+                        info.height,
+                        0,
+                        format,
+                        typ,
+                        face_data as *const _,
+                    ),
+                }
+            }
+        }
+        (TextureKind::Texture2D, Some(size)) => gl::CompressedTexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            texture_internal_format(&info.format),
+            info.width,
+            info.height,
+            0,
+            size,
+            c_data as *const _,
+        ),
+        (TextureKind::Array { layers }, Some(size)) => gl::CompressedTexImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            texture_internal_format(&info.format),
+            info.width,
+            info.height,
+            layers as _,
+            0,
+            size,
+            c_data as *const _,
+        ),
+        (TextureKind::Texture2D, None) => gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            texture_internal_format(&info.format) as _,
+            info.width,
+            info.height,
+            0,
+            format,
+            typ,
+            c_data as *const _,
+        ),
+        (TextureKind::Array { layers }, None) => gl::TexImage3D(
+            gl::TEXTURE_2D_ARRAY,
+            0,
+            texture_internal_format(&info.format) as _,
+            info.width,
+            info.height,
+            layers as _,
+            0,
+            format,
+            typ,
+            c_data as *const _,
+        ),
+    }
+
+    if info.mipmaps && !depth {
+        let (base, max) = info.mip_levels();
+        gl::TexParameteri(target, gl::TEXTURE_BASE_LEVEL, base as _);
+        gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, max as _);
+        gl::GenerateMipmap(target);
+    }
+
+    gl::BindTexture(target, 0);
 
     Ok(texture)
 }
 
+/// Immutable storage with all the mip levels, needed to bind the texture as an image
+unsafe fn texture_storage(info: &TextureInfo, target: u32, data: *const u8, format: u32, typ: u32) {
+    let internal =
+        image_format(&info.format).unwrap_or_else(|| texture_internal_format(&info.format));
+    let levels = info.mip_count() as i32;
+    let (width, height) = (info.width, info.height);
+
+    match info.kind {
+        TextureKind::Array { layers } => {
+            gl::TexStorage3D(target, levels, internal, width, height, layers as _);
+            if !data.is_null() {
+                gl::TexSubImage3D(
+                    target,
+                    0,
+                    0,
+                    0,
+                    0,
+                    width,
+                    height,
+                    layers as _,
+                    format,
+                    typ,
+                    data as *const _,
+                );
+            }
+        }
+        kind => {
+            gl::TexStorage2D(target, levels, internal, width, height);
+            if data.is_null() {
+                return;
+            }
+
+            let face_size = (width * height) as usize * info.bytes_per_pixel() as usize;
+            let faces = match kind {
+                TextureKind::Cube => (0..6)
+                    .map(|face| (gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, face as usize))
+                    .collect::<Vec<_>>(),
+                _ => vec![(target, 0)],
+            };
+
+            faces.into_iter().for_each(|(face_target, face)| {
+                gl::TexSubImage2D(
+                    face_target,
+                    0,
+                    0,
+                    0,
+                    width,
+                    height,
+                    format,
+                    typ,
+                    data.add(face * face_size) as *const _,
+                );
+            });
+        }
+    }
+}
+
+/// Sized format used to bind the texture as an image, `None` if it can't be
+pub(crate) fn image_format(tf: &TextureFormat) -> Option<u32> {
+    match tf {
+        TextureFormat::Rgba32 => Some(gl::RGBA8),
+        TextureFormat::Rgba16F => Some(gl::RGBA16F),
+        TextureFormat::Rgba32F => Some(gl::RGBA32F),
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn texture_target(kind: &TextureKind) -> u32 {
+    match kind {
+        TextureKind::Texture2D => gl::TEXTURE_2D,
+        TextureKind::Array { .. } => gl::TEXTURE_2D_ARRAY,
+        TextureKind::Cube => gl::TEXTURE_CUBE_MAP,
+    }
+}
+
+#[inline]
+fn min_filter(info: &TextureInfo) -> u32 {
+    mipmap_filter(&info.min_filter, info.mipmaps)
+}
+
+#[inline]
+fn mipmap_filter(filter: &TextureFilter, mipmaps: bool) -> u32 {
+    if !mipmaps {
+        return filter.to_gl();
+    }
+
+    match filter {
+        TextureFilter::Linear => gl::LINEAR_MIPMAP_LINEAR,
+        TextureFilter::Nearest => gl::NEAREST_MIPMAP_NEAREST,
+    }
+}
+
+pub(crate) unsafe fn create_sampler(_context: &Context, info: &SamplerInfo) -> u32 {
+    let mut sampler = 0;
+    gl::GenSamplers(1, &mut sampler);
+
+    let min_filter = mipmap_filter(&info.min_filter, info.mipmaps);
+    gl::SamplerParameteri(sampler, gl::TEXTURE_MIN_FILTER, min_filter as _);
+    gl::SamplerParameteri(
+        sampler,
+        gl::TEXTURE_MAG_FILTER,
+        info.mag_filter.to_gl() as _,
+    );
+    gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+    gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+
+    sampler
+}
+
+/// `KHR_texture_compression_astc_ldr`, not part of GLES 3.1 so it's missing in the bindings
+const COMPRESSED_RGBA_ASTC_4X4_KHR: u32 = 0x93B0;
+
+/// Compressed formats supported by the implementation
+pub(crate) fn compressed_formats() -> Vec<u32> {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_COMPRESSED_TEXTURE_FORMATS, &mut count);
+
+        let mut formats = vec![0; count.max(0) as usize];
+        if !formats.is_empty() {
+            gl::GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, formats.as_mut_ptr());
+        }
+        formats.into_iter().map(|f| f as u32).collect()
+    }
+}
+
 pub(crate) fn texture_format(tf: &TextureFormat) -> u32 {
     match tf {
-        TextureFormat::Rgba32 => gl::RGBA,
-        TextureFormat::R8 => gl::RED,
-        TextureFormat::Depth16 => gl::DEPTH_COMPONENT16,
+        TextureFormat::Rgba32
+        | TextureFormat::Rgba16F
+        | TextureFormat::Rgba32F
+        | TextureFormat::Astc4x4 => gl::RGBA,
+        TextureFormat::Etc2Rgb8 => gl::RGB,
+        TextureFormat::R8 | TextureFormat::R16F => gl::RED,
+        TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F => {
+            gl::DEPTH_COMPONENT
+        }
     }
 }
 
 pub(crate) fn texture_internal_format(tf: &TextureFormat) -> u32 {
     match tf {
         TextureFormat::R8 => gl::R8,
+        TextureFormat::R16F => gl::R16F,
+        TextureFormat::Rgba16F => gl::RGBA16F,
+        TextureFormat::Rgba32F => gl::RGBA32F,
+        TextureFormat::Depth16 => gl::DEPTH_COMPONENT16,
+        TextureFormat::Depth24 => gl::DEPTH_COMPONENT24,
+        TextureFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+        TextureFormat::Etc2Rgb8 => gl::COMPRESSED_RGB8_ETC2,
+        TextureFormat::Astc4x4 => COMPRESSED_RGBA_ASTC_4X4_KHR,
         _ => texture_format(tf),
     }
 }
+
+/// Data type of the pixel bytes for each format
+pub(crate) fn texture_type(tf: &TextureFormat) -> u32 {
+    match tf {
+        TextureFormat::Rgba32
+        | TextureFormat::R8
+        | TextureFormat::Etc2Rgb8
+        | TextureFormat::Astc4x4 => gl::UNSIGNED_BYTE,
+        TextureFormat::Depth16 => gl::UNSIGNED_SHORT,
+        TextureFormat::Depth24 => gl::UNSIGNED_INT,
+        TextureFormat::R16F | TextureFormat::Rgba16F => gl::HALF_FLOAT,
+        TextureFormat::Rgba32F | TextureFormat::Depth32F => gl::FLOAT,
+    }
+}
+
+/// GLES only guarantees reading RGBA and one format chosen by the implementation
+pub(crate) unsafe fn is_readable(format: u32, typ: u32) -> bool {
+    if format == gl::RGBA && (typ == gl::UNSIGNED_BYTE || typ == gl::FLOAT) {
+        return true;
+    }
+
+    let mut read_format = 0;
+    let mut read_type = 0;
+    gl::GetIntegerv(gl::IMPLEMENTATION_COLOR_READ_FORMAT, &mut read_format);
+    gl::GetIntegerv(gl::IMPLEMENTATION_COLOR_READ_TYPE, &mut read_type);
+    read_format as u32 == format && read_type as u32 == typ
+}
+
+/// Read the pixels of the bound framebuffer as RGBA and convert them to the requested format
+pub(crate) unsafe fn read_pixels_converted(
+    opts: &TextureRead,
+    bytes: &mut [u8],
+) -> Result<(), String> {
+    let pixels = (opts.width * opts.height) as usize;
+    match opts.format {
+        TextureFormat::R8 => {
+            let mut rgba = vec![0u8; pixels * 4];
+            gl::ReadPixels(
+                opts.x_offset,
+                opts.y_offset,
+                opts.width,
+                opts.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                rgba.as_mut_ptr() as *mut _,
+            );
+
+            bytes
+                .iter_mut()
+                .zip(rgba.chunks_exact(4))
+                .for_each(|(b, px)| *b = px[0]);
+        }
+        TextureFormat::R16F | TextureFormat::Rgba16F => {
+            let channels = if opts.format == TextureFormat::R16F {
+                1
+            } else {
+                4
+            };
+            let mut rgba = vec![0f32; pixels * 4];
+            gl::ReadPixels(
+                opts.x_offset,
+                opts.y_offset,
+                opts.width,
+                opts.height,
+                gl::RGBA,
+                gl::FLOAT,
+                rgba.as_mut_ptr() as *mut _,
+            );
+
+            let values = rgba.chunks_exact(4).flat_map(|px| &px[..channels]);
+            bytes
+                .chunks_exact_mut(2)
+                .zip(values)
+                .for_each(|(b, v)| b.copy_from_slice(&f32_to_f16(*v).to_ne_bytes()));
+        }
+        _ => return Err(format!("Pixels can't be read as {:?}", opts.format)),
+    }
+
+    match gl::GetError() {
+        gl::NO_ERROR => Ok(()),
+        err => Err(format!(
+            "Error reading pixels as {:?}: 0x{:x}",
+            opts.format, err
+        )),
+    }
+}
+
+/// Bits of a half float, rounding toward zero
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let mantissa = bits & 0x7f_ffff;
+    let exp = ((bits >> 23) & 0xff) as i32;
+
+    if exp == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exp = exp - 127 + 15;
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+
+        return sign | ((mantissa | 0x80_0000) >> (14 - exp)) as u16;
+    }
+
+    sign | ((exp as u16) << 10) | (mantissa >> 13) as u16
+}
@@ -1,9 +1,10 @@
 use crate::gfx::{
-    buffer::{BufferUsage, VertexFormat},
+    barrier::BarrierFlags,
+    buffer::{BufferUsage, IndexFormat, VertexFormat},
     pipeline::{
         BlendFactor, BlendOperation, CompareMode, CullMode, DrawPrimitive, DrawType, StencilAction,
     },
-    texture::TextureFilter,
+    texture::{ImageAccess, MipmapHint, Swizzle, TextureFilter},
 };
 
 use super::gl;
@@ -53,7 +54,7 @@ impl ToGl for BlendFactor {
             BlendFactor::InverseSourceAlpha => gl::ONE_MINUS_SRC_ALPHA,
             BlendFactor::InverseSourceColor => gl::ONE_MINUS_SRC_COLOR,
             BlendFactor::DestinationAlpha => gl::DST_ALPHA,
-            BlendFactor::DestinationColor => gl::SRC_COLOR,
+            BlendFactor::DestinationColor => gl::DST_COLOR,
             BlendFactor::InverseDestinationAlpha => gl::ONE_MINUS_DST_ALPHA,
             BlendFactor::InverseDestinationColor => gl::ONE_MINUS_DST_COLOR,
         }
@@ -81,6 +82,7 @@ impl ToOptionalGl for CullMode {
             CullMode::None => return Option::None,
             CullMode::Front => gl::FRONT,
             CullMode::Back => gl::BACK,
+            CullMode::FrontAndBack => gl::FRONT_AND_BACK,
         })
     }
 }
@@ -100,6 +102,17 @@ impl ToGl for BufferUsage {
             BufferUsage::Vertex => gl::ARRAY_BUFFER,
             BufferUsage::Index => gl::ELEMENT_ARRAY_BUFFER,
             BufferUsage::Uniform(_) => gl::UNIFORM_BUFFER,
+            BufferUsage::Storage(_) => gl::SHADER_STORAGE_BUFFER,
+        }
+    }
+}
+
+impl ToGl for IndexFormat {
+    fn to_gl(&self) -> u32 {
+        match self {
+            IndexFormat::UInt8 => gl::UNSIGNED_BYTE,
+            IndexFormat::UInt16 => gl::UNSIGNED_SHORT,
+            IndexFormat::UInt32 => gl::UNSIGNED_INT,
         }
     }
 }
@@ -108,10 +121,17 @@ impl ToGl for VertexFormat {
     fn to_gl(&self) -> u32 {
         match &self {
             VertexFormat::UInt8
+            | VertexFormat::UInt8Norm
             | VertexFormat::UInt8x2
+            | VertexFormat::UInt8x2Norm
             | VertexFormat::UInt8x3
-            | VertexFormat::UInt8x4 => gl::UNSIGNED_BYTE,
-            _ => gl::FLOAT,
+            | VertexFormat::UInt8x3Norm
+            | VertexFormat::UInt8x4
+            | VertexFormat::UInt8x4Norm => gl::UNSIGNED_BYTE,
+            VertexFormat::Float32
+            | VertexFormat::Float32x2
+            | VertexFormat::Float32x3
+            | VertexFormat::Float32x4 => gl::FLOAT,
         }
     }
 }
@@ -125,9 +145,43 @@ impl ToGl for TextureFilter {
     }
 }
 
+impl ToGl for ImageAccess {
+    fn to_gl(&self) -> u32 {
+        match self {
+            ImageAccess::Read => gl::READ_ONLY,
+            ImageAccess::Write => gl::WRITE_ONLY,
+            ImageAccess::ReadWrite => gl::READ_WRITE,
+        }
+    }
+}
+
+impl ToGl for MipmapHint {
+    fn to_gl(&self) -> u32 {
+        match self {
+            MipmapHint::Fastest => gl::FASTEST,
+            MipmapHint::Nicest => gl::NICEST,
+            MipmapHint::DontCare => gl::DONT_CARE,
+        }
+    }
+}
+
+impl ToGl for Swizzle {
+    fn to_gl(&self) -> u32 {
+        match self {
+            Swizzle::Red => gl::RED,
+            Swizzle::Green => gl::GREEN,
+            Swizzle::Blue => gl::BLUE,
+            Swizzle::Alpha => gl::ALPHA,
+            Swizzle::Zero => gl::ZERO,
+            Swizzle::One => gl::ONE,
+        }
+    }
+}
+
 impl ToGl for DrawPrimitive {
     fn to_gl(&self) -> u32 {
         match self {
+            DrawPrimitive::Points => gl::POINTS,
             DrawPrimitive::Triangles => gl::TRIANGLES,
             DrawPrimitive::TriangleStrip => gl::TRIANGLE_STRIP,
             DrawPrimitive::Lines => gl::LINES,
@@ -135,3 +189,179 @@ impl ToGl for DrawPrimitive {
         }
     }
 }
+
+impl ToGl for BarrierFlags {
+    fn to_gl(&self) -> u32 {
+        // also covers the bits of the extensions without a flag
+        if *self == BarrierFlags::ALL {
+            return gl::ALL_BARRIER_BITS;
+        }
+
+        [
+            (self.vertex_attrib, gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT),
+            (self.element_array, gl::ELEMENT_ARRAY_BARRIER_BIT),
+            (self.uniform, gl::UNIFORM_BARRIER_BIT),
+            (self.texture_fetch, gl::TEXTURE_FETCH_BARRIER_BIT),
+            (
+                self.shader_image_access,
+                gl::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+            ),
+            (self.command, gl::COMMAND_BARRIER_BIT),
+            (self.buffer_update, gl::BUFFER_UPDATE_BARRIER_BIT),
+            (self.texture_update, gl::TEXTURE_UPDATE_BARRIER_BIT),
+            (self.framebuffer, gl::FRAMEBUFFER_BARRIER_BIT),
+            (self.shader_storage, gl::SHADER_STORAGE_BARRIER_BIT),
+            (self.pixel_buffer, gl::PIXEL_BUFFER_BARRIER_BIT),
+            (self.transform_feedback, gl::TRANSFORM_FEEDBACK_BARRIER_BIT),
+            (self.atomic_counter, gl::ATOMIC_COUNTER_BARRIER_BIT),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |bits, (_, bit)| bits | bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stencil_action() {
+        assert_eq!(StencilAction::Keep.to_gl(), gl::KEEP);
+        assert_eq!(StencilAction::Zero.to_gl(), gl::ZERO);
+        assert_eq!(StencilAction::Replace.to_gl(), gl::REPLACE);
+        assert_eq!(StencilAction::Increment.to_gl(), gl::INCR);
+        assert_eq!(StencilAction::IncrementWrap.to_gl(), gl::INCR_WRAP);
+        assert_eq!(StencilAction::Decrement.to_gl(), gl::DECR);
+        assert_eq!(StencilAction::DecrementWrap.to_gl(), gl::DECR_WRAP);
+        assert_eq!(StencilAction::Invert.to_gl(), gl::INVERT);
+    }
+
+    #[test]
+    fn blend_operation() {
+        assert_eq!(BlendOperation::Add.to_gl(), gl::FUNC_ADD);
+        assert_eq!(BlendOperation::Subtract.to_gl(), gl::FUNC_SUBTRACT);
+        assert_eq!(
+            BlendOperation::ReverseSubtract.to_gl(),
+            gl::FUNC_REVERSE_SUBTRACT
+        );
+        assert_eq!(BlendOperation::Min.to_gl(), gl::MIN);
+        assert_eq!(BlendOperation::Max.to_gl(), gl::MAX);
+    }
+
+    #[test]
+    fn blend_factor() {
+        assert_eq!(BlendFactor::Zero.to_gl(), gl::ZERO);
+        assert_eq!(BlendFactor::One.to_gl(), gl::ONE);
+        assert_eq!(BlendFactor::SourceColor.to_gl(), gl::SRC_COLOR);
+        assert_eq!(
+            BlendFactor::InverseSourceColor.to_gl(),
+            gl::ONE_MINUS_SRC_COLOR
+        );
+        assert_eq!(BlendFactor::DestinationColor.to_gl(), gl::DST_COLOR);
+        assert_eq!(
+            BlendFactor::InverseDestinationColor.to_gl(),
+            gl::ONE_MINUS_DST_COLOR
+        );
+        assert_eq!(BlendFactor::SourceAlpha.to_gl(), gl::SRC_ALPHA);
+        assert_eq!(
+            BlendFactor::InverseSourceAlpha.to_gl(),
+            gl::ONE_MINUS_SRC_ALPHA
+        );
+        assert_eq!(BlendFactor::DestinationAlpha.to_gl(), gl::DST_ALPHA);
+        assert_eq!(
+            BlendFactor::InverseDestinationAlpha.to_gl(),
+            gl::ONE_MINUS_DST_ALPHA
+        );
+    }
+
+    #[test]
+    fn compare_mode() {
+        assert_eq!(CompareMode::None.to_gl(), None);
+        assert_eq!(CompareMode::Less.to_gl(), Some(gl::LESS));
+        assert_eq!(CompareMode::Equal.to_gl(), Some(gl::EQUAL));
+        assert_eq!(CompareMode::LEqual.to_gl(), Some(gl::LEQUAL));
+        assert_eq!(CompareMode::Greater.to_gl(), Some(gl::GREATER));
+        assert_eq!(CompareMode::NotEqual.to_gl(), Some(gl::NOTEQUAL));
+        assert_eq!(CompareMode::GEqual.to_gl(), Some(gl::GEQUAL));
+        assert_eq!(CompareMode::Always.to_gl(), Some(gl::ALWAYS));
+    }
+
+    #[test]
+    fn cull_mode() {
+        assert_eq!(CullMode::None.to_gl(), None);
+        assert_eq!(CullMode::Front.to_gl(), Some(gl::FRONT));
+        assert_eq!(CullMode::Back.to_gl(), Some(gl::BACK));
+        assert_eq!(CullMode::FrontAndBack.to_gl(), Some(gl::FRONT_AND_BACK));
+    }
+
+    #[test]
+    fn draw_primitive() {
+        assert_eq!(DrawPrimitive::Points.to_gl(), gl::POINTS);
+        assert_eq!(DrawPrimitive::Lines.to_gl(), gl::LINES);
+        assert_eq!(DrawPrimitive::LineStrip.to_gl(), gl::LINE_STRIP);
+        assert_eq!(DrawPrimitive::Triangles.to_gl(), gl::TRIANGLES);
+        assert_eq!(DrawPrimitive::TriangleStrip.to_gl(), gl::TRIANGLE_STRIP);
+    }
+
+    #[test]
+    fn vertex_format() {
+        let floats = [
+            VertexFormat::Float32,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x4,
+        ];
+        floats
+            .iter()
+            .for_each(|format| assert_eq!(format.to_gl(), gl::FLOAT, "{:?}", format));
+
+        let bytes = [
+            VertexFormat::UInt8,
+            VertexFormat::UInt8Norm,
+            VertexFormat::UInt8x2,
+            VertexFormat::UInt8x2Norm,
+            VertexFormat::UInt8x3,
+            VertexFormat::UInt8x3Norm,
+            VertexFormat::UInt8x4,
+            VertexFormat::UInt8x4Norm,
+        ];
+        bytes
+            .iter()
+            .for_each(|format| assert_eq!(format.to_gl(), gl::UNSIGNED_BYTE, "{:?}", format));
+    }
+
+    #[test]
+    fn image_access() {
+        assert_eq!(ImageAccess::Read.to_gl(), gl::READ_ONLY);
+        assert_eq!(ImageAccess::Write.to_gl(), gl::WRITE_ONLY);
+        assert_eq!(ImageAccess::ReadWrite.to_gl(), gl::READ_WRITE);
+    }
+
+    #[test]
+    fn barrier_flags() {
+        assert_eq!(BarrierFlags::NONE.to_gl(), 0);
+        assert_eq!(BarrierFlags::ALL.to_gl(), gl::ALL_BARRIER_BITS);
+        assert_eq!(
+            BarrierFlags::TEXTURE_SAMPLE.to_gl(),
+            gl::TEXTURE_FETCH_BARRIER_BIT
+        );
+        assert_eq!(
+            BarrierFlags::VERTEX_DATA.to_gl(),
+            gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT | gl::ELEMENT_ARRAY_BARRIER_BIT
+        );
+
+        let storage = BarrierFlags {
+            shader_storage: true,
+            ..BarrierFlags::NONE
+        };
+        let atomic = BarrierFlags {
+            atomic_counter: true,
+            ..BarrierFlags::NONE
+        };
+        assert_eq!(
+            (storage | atomic).to_gl(),
+            gl::SHADER_STORAGE_BARRIER_BIT | gl::ATOMIC_COUNTER_BARRIER_BIT
+        );
+    }
+}
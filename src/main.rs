@@ -1,16 +1,18 @@
 use crate::{
     gfx::{
-        buffer::{VertexFormat, VertexInfo},
+        buffer::{Buffer, VertexFormat, VertexInfo},
         color::Color,
         device::Device,
-        pipeline::ClearOptions,
+        pipeline::{ClearOptions, Pipeline},
     },
-    gfx_backend::GlesBackend,
+    gfx_backend::{BackendConfig, GlesBackend},
 };
 use camera::Camera;
+use clock::FrameClock;
 use glam::{vec3, Mat4, Quat, Vec3};
 use rand::Rng;
 use std::f32::consts::PI;
+#[cfg(not(all(feature = "app-handler", target_os = "linux")))]
 use winit::{
     event::Event,
     event_loop::{ControlFlow, EventLoop},
@@ -18,7 +20,10 @@ use winit::{
     window::WindowBuilder,
 };
 
+#[cfg(all(feature = "app-handler", target_os = "linux"))]
+mod app;
 mod camera;
+mod clock;
 mod gfx;
 mod gfx_backend;
 
@@ -52,102 +57,161 @@ const FRAG: &str = r#"
     }
 "#;
 
-fn main() {
-    let mut event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+/// Radians per second
+const ROTATION_SPEED: f32 = 0.3;
+
+/// Smooth the triangle edges with a multisampled window surface
+const BACKEND_CONFIG: BackendConfig = BackendConfig { msaa_samples: 4 };
+
+pub(crate) struct Demo {
+    device: Device<GlesBackend>,
+    clear_options: ClearOptions,
+    pipeline: Pipeline,
+    vbo: Buffer,
+    uniform_buffer: Buffer,
+    camera: Camera,
+    clock: FrameClock,
+    angle: f32,
+    offsets: Vec<(f32, f32, f32)>,
+}
 
-    let mut device = Device::new(GlesBackend::new(&window).unwrap());
+impl Demo {
+    pub(crate) fn new(backend: GlesBackend) -> Result<Self, String> {
+        let mut device = Device::new(backend);
+
+        let clear_options = ClearOptions::color(Color::new(0.1, 0.2, 0.3, 1.0));
+
+        let vertex_info = VertexInfo::new()
+            .attr(0, VertexFormat::Float32x3)
+            .attr(1, VertexFormat::Float32x3);
+
+        let pipeline = device
+            .create_pipeline()
+            .from(VERT, FRAG)
+            .with_vertex_info(&vertex_info)
+            .build()?;
+
+        #[rustfmt::skip]
+        let vertices = [
+             0.0,    0.5,  0.0,   1.0, 0.2, 0.3,
+             0.433, -0.25, 0.0,   0.1, 1.0, 0.3,
+            -0.433, -0.25, 0.0,   0.1, 0.2, 1.0,
+        ];
+
+        let vbo = device
+            .create_vertex_buffer()
+            .with_info(&vertex_info)
+            .with_data(&vertices)
+            .build()?;
+
+        let uniform_buffer = device.create_uniform_buffer(0, "Locals").build()?;
+
+        let mut offsets = Vec::new();
+
+        for _i in 0..1000 {
+            offsets.push((
+                rand::thread_rng().gen::<f32>() * 2.0 * PI,
+                rand::thread_rng().gen::<f32>() * 2.0 - 1.0,
+                rand::thread_rng().gen::<f32>() * 2.0 - 1.0,
+            ));
+        }
 
-    let clear_options = ClearOptions::color(Color::new(0.1, 0.2, 0.3, 1.0));
+        Ok(Self {
+            device,
+            clear_options,
+            pipeline,
+            vbo,
+            uniform_buffer,
+            camera: Camera::new(),
+            clock: FrameClock::new(),
+            angle: 0.0,
+            offsets,
+        })
+    }
 
-    let vertex_info = VertexInfo::new()
-        .attr(0, VertexFormat::Float32x3)
-        .attr(1, VertexFormat::Float32x3);
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            if let Err(err) = self.device.set_size(width as i32, height as i32) {
+                eprintln!("Failed to resize the device: {}", err);
+            }
+        }
+    }
 
-    let pipeline = device
-        .create_pipeline()
-        .from(VERT, FRAG)
-        .with_vertex_info(&vertex_info)
-        .build()
-        .unwrap();
+    /// Measure the frame time and render a new frame
+    pub(crate) fn redraw(&mut self) {
+        let dt = self.clock.tick();
+        self.update(dt);
+        self.draw();
+    }
 
-    #[rustfmt::skip]
-    let vertices = [
-         0.0,    0.5,  0.0,   1.0, 0.2, 0.3,
-         0.433, -0.25, 0.0,   0.1, 1.0, 0.3,
-        -0.433, -0.25, 0.0,   0.1, 0.2, 1.0,
-    ];
+    /// Advance the animation, `dt` in seconds can come from the host instead of the clock
+    pub(crate) fn update(&mut self, dt: f32) {
+        self.angle += ROTATION_SPEED * dt;
+    }
 
-    let vbo = device
-        .create_vertex_buffer()
-        .with_info(&vertex_info)
-        .with_data(&vertices)
-        .build()
-        .unwrap();
+    pub(crate) fn draw(&mut self) {
+        let (width, height) = self.device.size();
+        let mut encoder = self.device.command_encoder_pool().acquire(width, height);
+
+        let proj = self.camera.update(self.device.size());
+
+        let mvps = self
+            .offsets
+            .iter()
+            .map(|offset| {
+                proj * Mat4::from_scale_rotation_translation(
+                    Vec3::splat(0.1),
+                    Quat::from_rotation_z(self.angle + offset.0),
+                    vec3(offset.1, offset.2, -1.0),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        self.device.set_buffer_matrices(&self.uniform_buffer, &mvps);
+
+        encoder.pass(Some(&self.clear_options), |pass| {
+            pass.set_pipeline(&self.pipeline);
+            pass.bind_buffer(&self.vbo);
+            pass.bind_buffer(&self.uniform_buffer);
+            pass.draw_instanced(0, 3, self.offsets.len() as i32);
+        });
+
+        if let Err(err) = self.device.submit(&encoder) {
+            eprintln!("Failed to render the frame: {}", err);
+        }
+        self.device.command_encoder_pool().release(encoder);
 
-    let uniform_buffer = device.create_uniform_buffer(0, "Locals").build().unwrap();
+        self.device.swap_buffers();
 
-    let mut camera = Camera::new();
+        self.device.clean();
+    }
+}
 
-    let mut angle = 0.0;
+#[cfg(all(feature = "app-handler", target_os = "linux"))]
+fn main() {
+    app::run();
+}
 
-    let mut offsets = Vec::new();
+#[cfg(not(all(feature = "app-handler", target_os = "linux")))]
+fn main() {
+    let mut event_loop = EventLoop::new();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
 
-    for _i in 0..1000 {
-        offsets.push((
-            rand::thread_rng().gen::<f32>() * 2.0 * PI,
-            rand::thread_rng().gen::<f32>() * 2.0 - 1.0,
-            rand::thread_rng().gen::<f32>() * 2.0 - 1.0,
-        ));
-    }
+    let mut demo = Demo::new(GlesBackend::with_config(&window, &BACKEND_CONFIG).unwrap()).unwrap();
 
     event_loop.run_return(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
 
         match event {
             Event::RedrawRequested(_) => {
-                let mut mvps = Vec::new();
-
-                let mut encoder = device.create_command_encoder();
-
-                let proj = camera.update(device.size());
-
-                for offset in &offsets {
-                    let transform = Mat4::from_scale_rotation_translation(
-                        Vec3::splat(0.1),
-                        Quat::from_rotation_z(angle + offset.0),
-                        vec3(offset.1, offset.2, -1.0),
-                    );
-
-                    mvps.extend_from_slice(&(proj * transform).to_cols_array());
-                }
-
-                angle += 0.005;
-
-                device.set_buffer_data(&uniform_buffer, &mvps);
-
-                encoder.begin(Some(&clear_options));
-                encoder.set_pipeline(&pipeline);
-                encoder.bind_buffer(&vbo);
-                encoder.bind_buffer(&uniform_buffer);
-                encoder.draw(0, 3);
-                encoder.draw_instanced(0, 3, offsets.len() as i32);
-                encoder.end();
-
-                device.render(encoder.commands());
-
-                device.swap_buffers();
-
-                device.clean();
+                demo.redraw();
             }
             Event::MainEventsCleared => {
                 window.request_redraw();
             }
             Event::WindowEvent { event, window_id } => match event {
                 winit::event::WindowEvent::Resized(size) => {
-                    if size.width > 0 && size.height > 0 {
-                        device.set_size(size.width as i32, size.height as i32);
-                    }
+                    demo.resize(size.width, size.height);
                 }
                 winit::event::WindowEvent::CloseRequested => {
                     if window_id == window.id() {